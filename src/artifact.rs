@@ -0,0 +1,203 @@
+use crate::config::{Artifact, Cook};
+use crate::error::{CookError, Result};
+use crate::metadata::Package;
+
+const KINDS: &[&str] = &[
+    "bin",
+    "example",
+    "lib",
+    "rlib",
+    "dylib",
+    "cdylib",
+    "staticlib",
+];
+
+pub fn support_kind(kind: &str) -> bool {
+    KINDS.contains(&kind)
+}
+
+/// Returns the artifacts to cook: the ones from the recipe or all the
+/// binaries of the package.
+pub fn artifacts(c: &Cook, package: &Package) -> Vec<Artifact> {
+    if let Some(ref artifacts) = c.artifact {
+        return artifacts.clone();
+    }
+
+    let bins: Vec<&str> = package
+        .targets
+        .iter()
+        .filter(|t| t.kind.iter().any(|k| k == "bin"))
+        .map(|t| t.name.as_str())
+        .collect();
+    // The deprecated `target_rename` is only meaningful for a single binary.
+    let rename = if bins.len() == 1 {
+        c.target_rename.clone()
+    } else {
+        None
+    };
+    bins.into_iter()
+        .map(|name| Artifact {
+            name: name.to_owned(),
+            kind: "bin".to_owned(),
+            rename: rename.clone(),
+        })
+        .collect()
+}
+
+/// Returns the path of the artifact file relative to the artifact directory
+/// for the target triple.
+pub fn file_name(a: &Artifact, triple: &str) -> Result<String> {
+    let windows = triple.contains("windows");
+    let msvc = windows && triple.contains("msvc");
+    let apple = triple.contains("apple") || triple.contains("darwin");
+    let wasm = triple.starts_with("wasm");
+    // Libraries have their hyphens replaced by underscores.
+    let lib = a.name.replace('-', "_");
+
+    let executable = |name: &str| {
+        if windows {
+            format!("{}.exe", name)
+        } else if wasm {
+            format!("{}.wasm", name)
+        } else {
+            name.to_owned()
+        }
+    };
+
+    Ok(match a.kind.as_str() {
+        "bin" => executable(&a.name),
+        "example" => format!("examples/{}", executable(&a.name)),
+        "lib" | "rlib" => format!("lib{}.rlib", lib),
+        "dylib" | "cdylib" if windows => format!("{}.dll", lib),
+        "dylib" | "cdylib" if apple => format!("lib{}.dylib", lib),
+        "dylib" | "cdylib" if wasm => format!("{}.wasm", lib),
+        "dylib" | "cdylib" => format!("lib{}.so", lib),
+        "staticlib" if msvc => format!("{}.lib", lib),
+        "staticlib" => format!("lib{}.a", lib),
+        k => {
+            return Err(CookError::Config(format!(
+                "The \"{}\" artifact kind is unsupported.",
+                k
+            )))
+        }
+    })
+}
+
+/// Returns the cargo build arguments which select the artifacts.
+pub fn build_args(artifacts: &[Artifact]) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut lib = false;
+    for a in artifacts {
+        match a.kind.as_str() {
+            "bin" | "example" => {
+                args.push(format!("--{}", a.kind));
+                args.push(a.name.clone());
+            }
+            _ => lib = true,
+        }
+    }
+    if lib {
+        args.push("--lib".to_owned());
+    }
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(triple: &str) -> Vec<String> {
+        KINDS
+            .iter()
+            .map(|k| {
+                let a = Artifact {
+                    name: "my-crate".to_owned(),
+                    kind: (*k).to_owned(),
+                    rename: None,
+                };
+                file_name(&a, triple).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn file_names() {
+        // In the order of the kinds: bin, example, lib, rlib, dylib, cdylib
+        // and staticlib.
+        assert_eq!(
+            names("x86_64-unknown-linux-gnu"),
+            [
+                "my-crate",
+                "examples/my-crate",
+                "libmy_crate.rlib",
+                "libmy_crate.rlib",
+                "libmy_crate.so",
+                "libmy_crate.so",
+                "libmy_crate.a"
+            ]
+        );
+        assert_eq!(
+            names("x86_64-pc-windows-msvc"),
+            [
+                "my-crate.exe",
+                "examples/my-crate.exe",
+                "libmy_crate.rlib",
+                "libmy_crate.rlib",
+                "my_crate.dll",
+                "my_crate.dll",
+                "my_crate.lib"
+            ]
+        );
+        assert_eq!(
+            names("x86_64-pc-windows-gnu"),
+            [
+                "my-crate.exe",
+                "examples/my-crate.exe",
+                "libmy_crate.rlib",
+                "libmy_crate.rlib",
+                "my_crate.dll",
+                "my_crate.dll",
+                "libmy_crate.a"
+            ]
+        );
+        assert_eq!(
+            names("aarch64-apple-darwin"),
+            [
+                "my-crate",
+                "examples/my-crate",
+                "libmy_crate.rlib",
+                "libmy_crate.rlib",
+                "libmy_crate.dylib",
+                "libmy_crate.dylib",
+                "libmy_crate.a"
+            ]
+        );
+        assert_eq!(
+            names("wasm32-unknown-unknown"),
+            [
+                "my-crate.wasm",
+                "examples/my-crate.wasm",
+                "libmy_crate.rlib",
+                "libmy_crate.rlib",
+                "my_crate.wasm",
+                "my_crate.wasm",
+                "libmy_crate.a"
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_kind() {
+        let a = Artifact {
+            name: "my-crate".to_owned(),
+            kind: "exe".to_owned(),
+            rename: None,
+        };
+        assert_eq!(
+            file_name(&a, "x86_64-unknown-linux-gnu"),
+            Err(CookError::Config(
+                "The \"exe\" artifact kind is unsupported.".to_owned()
+            ))
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use crate::artifact;
+use crate::config::{Artifact, Build, Cook};
+use crate::error::{CookError, Result};
+use crate::term_print::*;
+use std::env;
+use std::process::Command;
+
+const DEFAULT_PROFILE: &str = "release";
+const BUILD_LABEL: &str = "Building";
+
+/// Returns the cargo binary, cargo tells its subcommands which one has invoked them.
+pub fn cargo() -> String {
+    env::var("CARGO").unwrap_or_else(|_| "cargo".to_owned())
+}
+
+/// Returns the target triple of the host as rustc reports it.
+pub fn host_triple() -> Result<String> {
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_owned());
+    let output = Command::new(&rustc)
+        .arg("-vV")
+        .output()
+        .map_err(|e| CookError::Metadata(format!("Unable to run {}: {}", rustc, e)))?;
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|l| l.strip_prefix("host: "))
+        .map(|h| h.trim().to_owned())
+        .ok_or_else(|| {
+            CookError::Metadata(format!("Unable to get the host triple from {}.", rustc))
+        })
+}
+
+/// Returns the cargo profile the crate is built with.
+pub fn profile(b: &Build) -> &str {
+    b.profile.as_deref().unwrap_or(DEFAULT_PROFILE)
+}
+
+/// Returns the cargo profile the recipe builds the crate with.
+pub fn recipe_profile(c: &Cook) -> &str {
+    c.build.as_ref().map(profile).unwrap_or(DEFAULT_PROFILE)
+}
+
+/// Returns the name of the directory cargo puts the artifacts of a profile to.
+fn profile_directory(profile: &str) -> &str {
+    match profile {
+        "dev" | "test" => "debug",
+        "bench" => "release",
+        p => p,
+    }
+}
+
+/// Returns the directory with the crate artifacts built for the target triple.
+pub fn artifact_directory(c: &Cook, target_directory: &str, triple: Option<&str>) -> String {
+    if let Some(ref d) = c.target_directory {
+        return d.clone();
+    }
+    let profile = recipe_profile(c);
+    match triple {
+        Some(t) => format!("{}/{}/{}", target_directory, t, profile_directory(profile)),
+        None => format!("{}/{}", target_directory, profile_directory(profile)),
+    }
+}
+
+fn args(b: &Build, package: &str, triple: Option<&str>, artifacts: &[Artifact]) -> Vec<String> {
+    let mut args = vec![
+        "build".to_owned(),
+        "--package".to_owned(),
+        package.to_owned(),
+        "--profile".to_owned(),
+        profile(b).to_owned(),
+    ];
+    if let Some(t) = triple {
+        args.push("--target".to_owned());
+        args.push(t.to_owned());
+    }
+    args.extend(artifact::build_args(artifacts));
+    if let Some(ref features) = b.features {
+        if !features.is_empty() {
+            args.push("--features".to_owned());
+            args.push(features.join(","));
+        }
+    }
+    if b.all_features.unwrap_or(false) {
+        args.push("--all-features".to_owned());
+    }
+    if b.no_default_features.unwrap_or(false) {
+        args.push("--no-default-features".to_owned());
+    }
+    if let Some(ref extra) = b.args {
+        args.extend(extra.iter().cloned());
+    }
+    args
+}
+
+/// Builds the package artifacts with cargo.
+/// All the default package targets are built if no artifacts are specified.
+pub fn build(
+    b: &Build,
+    package: &str,
+    triple: Option<&str>,
+    artifacts: &[Artifact],
+    dry_run: bool,
+) -> Result<()> {
+    let cargo = cargo();
+    let args = args(b, package, triple, artifacts);
+    if dry_run {
+        term_println(
+            term::color::YELLOW,
+            "Would build",
+            &format!("cargo {}", args.join(" ")),
+        );
+        return Ok(());
+    }
+
+    term_println(
+        term::color::YELLOW,
+        BUILD_LABEL,
+        &format!("cargo {}", args.join(" ")),
+    );
+    match Command::new(&cargo)
+        .args(&args)
+        .stdout(crate::message::child_stdout())
+        .status()
+    {
+        Ok(s) if s.success() => {
+            term_println(
+                term::color::BRIGHT_GREEN,
+                "Built",
+                &format!("with the \"{}\" profile", profile(b)),
+            );
+            Ok(())
+        }
+        Ok(s) => Err(CookError::Build(format!(
+            "Build failed: cargo returned {}",
+            s.code().unwrap_or(-1i32)
+        ))),
+        Err(e) => Err(CookError::Build(format!("Unable to run {}: {}", cargo, e))),
+    }
+}
@@ -1,4 +1,17 @@
-#[derive(Default, Debug, Clone, Eq, PartialEq, Ord, PartialOrd, serde::Deserialize)]
+use crate::error::{CookError, Result};
+use crate::validate;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const COOK_KEY: &str = "cook";
+/// The recipe to put the `[cook]` table on top of.
+const EXTENDS_KEY: &str = "extends";
+/// The recipes to put on top of the extended one, before the `[cook]` table.
+const INCLUDE_KEY: &str = "include";
+
+#[derive(Default, Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Deserialize, Serialize)]
 pub struct CookIngredient {
     pub source: String,
     pub filter: Option<String>,
@@ -6,7 +19,7 @@ pub struct CookIngredient {
 }
 
 #[cfg(feature = "ssh")]
-#[derive(Default, Debug, Clone, Eq, PartialEq, Ord, PartialOrd, serde::Deserialize)]
+#[derive(Default, Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Deserialize, Serialize)]
 pub struct SshConfig {
     pub hostname: String,
     pub username: String,
@@ -14,13 +27,13 @@ pub struct SshConfig {
     pub deploy_script: Option<String>,
 }
 
-#[derive(Default, Debug, Clone, Eq, PartialEq, Ord, PartialOrd, serde::Deserialize)]
+#[derive(Default, Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Deserialize, Serialize)]
 pub struct FsCopy {
     pub path: String,
 }
 
 #[cfg(feature = "deploy")]
-#[derive(Default, Debug, Clone, Eq, PartialEq, Ord, PartialOrd, serde::Deserialize)]
+#[derive(Default, Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Deserialize, Serialize)]
 pub struct Deploy {
     pub targets: Option<Vec<String>>,
     #[cfg(feature = "ssh")]
@@ -28,33 +41,776 @@ pub struct Deploy {
     pub fscopy: Option<FsCopy>,
 }
 
-#[derive(Default, Debug, Clone, Eq, PartialEq, Ord, PartialOrd, serde::Deserialize)]
+#[derive(Default, Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Deserialize, Serialize)]
+pub struct Artifact {
+    pub name: String,
+    pub kind: String,
+    pub rename: Option<String>,
+}
+
+#[derive(Default, Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Deserialize, Serialize)]
+pub struct Build {
+    pub profile: Option<String>,
+    pub features: Option<Vec<String>>,
+    pub all_features: Option<bool>,
+    pub no_default_features: Option<bool>,
+    pub args: Option<Vec<String>>,
+}
+
+/// The settings of a container type.
+#[derive(Default, Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Deserialize, Serialize)]
+pub struct Container {
+    pub archive_name: Option<String>,
+    pub level: Option<u32>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct Cook {
-    pub target_directory: String,
+    pub target_directory: Option<String>,
+    pub targets: Option<Vec<String>>,
     pub target_rename: Option<String>,
     pub hashes: Option<Vec<String>>,
     pub containers: Vec<String>,
     pub pre_cook: Option<String>,
     pub post_cook: Option<String>,
     pub include_dependencies: Option<bool>,
+    pub library_paths: Option<Vec<String>>,
+    pub rpath: Option<String>,
     pub cook_directory: String,
+    pub archive_name: Option<String>,
+    pub reproducible: Option<bool>,
+    pub build: Option<Build>,
+    pub container: Option<BTreeMap<String, Container>>,
     #[cfg(feature = "deploy")]
     pub deploy: Option<Deploy>,
     pub ingredient: Option<Vec<CookIngredient>>,
+    pub artifact: Option<Vec<Artifact>>,
+    pub member: Option<BTreeMap<String, toml::Value>>,
+    pub profile: Option<BTreeMap<String, toml::Value>>,
 }
 
-#[derive(Default, Debug, Clone, Eq, PartialEq, Ord, PartialOrd, serde::Deserialize)]
-pub struct Package {
-    pub name: String,
-    pub version: String,
-}
-
-#[derive(Default, Debug, Clone, Eq, PartialEq, Ord, PartialOrd, serde::Deserialize)]
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct CookConfig {
     pub cook: Cook,
 }
 
-#[derive(Default, Debug, Clone, Eq, PartialEq, Ord, PartialOrd, serde::Deserialize)]
-pub struct CargoConfig {
-    pub package: Package,
+/// The type of a recipe field.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum FieldType {
+    String,
+    Bool,
+    Integer,
+    StringList,
+    /// A table with the fields of its own.
+    Table,
+    /// An array of tables.
+    TableList,
+    /// A table of the tables with the user-chosen names, `*` in the paths.
+    Map,
+    /// The overrides of the recipe, which have the fields of `[cook]`.
+    Recipe,
+}
+
+impl FieldType {
+    pub fn matches(self, value: &toml::Value) -> bool {
+        use toml::Value;
+
+        match (self, value) {
+            (FieldType::String, Value::String(_))
+            | (FieldType::Bool, Value::Boolean(_))
+            | (FieldType::Integer, Value::Integer(_)) => true,
+            (FieldType::StringList, Value::Array(a)) => a.iter().all(|v| v.is_str()),
+            (FieldType::TableList, Value::Array(a)) => a.iter().all(|v| v.is_table()),
+            (FieldType::Table, Value::Table(_))
+            | (FieldType::Map, Value::Table(_))
+            | (FieldType::Recipe, Value::Table(_)) => true,
+            _ => false,
+        }
+    }
+
+    pub fn describe(self) -> &'static str {
+        match self {
+            FieldType::String => "a string",
+            FieldType::Bool => "a boolean",
+            FieldType::Integer => "an integer",
+            FieldType::StringList => "a list of strings",
+            FieldType::Table | FieldType::Map | FieldType::Recipe => "a table",
+            FieldType::TableList => "an array of tables",
+        }
+    }
+}
+
+/// The fields of the `[cook]` table, the paths are relative to it.
+pub const FIELDS: &[(&str, FieldType)] = &[
+    ("target_directory", FieldType::String),
+    ("targets", FieldType::StringList),
+    ("target_rename", FieldType::String),
+    ("hashes", FieldType::StringList),
+    ("containers", FieldType::StringList),
+    ("pre_cook", FieldType::String),
+    ("post_cook", FieldType::String),
+    ("include_dependencies", FieldType::Bool),
+    ("library_paths", FieldType::StringList),
+    ("rpath", FieldType::String),
+    ("cook_directory", FieldType::String),
+    ("archive_name", FieldType::String),
+    ("reproducible", FieldType::Bool),
+    (EXTENDS_KEY, FieldType::String),
+    (INCLUDE_KEY, FieldType::StringList),
+    ("build", FieldType::Table),
+    ("build.profile", FieldType::String),
+    ("build.features", FieldType::StringList),
+    ("build.all_features", FieldType::Bool),
+    ("build.no_default_features", FieldType::Bool),
+    ("build.args", FieldType::StringList),
+    ("container", FieldType::Map),
+    ("container.*", FieldType::Table),
+    ("container.*.archive_name", FieldType::String),
+    ("container.*.level", FieldType::Integer),
+    ("deploy", FieldType::Table),
+    ("deploy.targets", FieldType::StringList),
+    ("deploy.ssh", FieldType::Table),
+    ("deploy.ssh.hostname", FieldType::String),
+    ("deploy.ssh.username", FieldType::String),
+    ("deploy.ssh.remote_path", FieldType::String),
+    ("deploy.ssh.deploy_script", FieldType::String),
+    ("deploy.fscopy", FieldType::Table),
+    ("deploy.fscopy.path", FieldType::String),
+    ("ingredient", FieldType::TableList),
+    ("ingredient.source", FieldType::String),
+    ("ingredient.filter", FieldType::String),
+    ("ingredient.destination", FieldType::String),
+    ("artifact", FieldType::TableList),
+    ("artifact.name", FieldType::String),
+    ("artifact.kind", FieldType::String),
+    ("artifact.rename", FieldType::String),
+    ("member", FieldType::Map),
+    ("member.*", FieldType::Recipe),
+    ("profile", FieldType::Map),
+    ("profile.*", FieldType::Recipe),
+];
+
+/// Returns the type of the field, the path is relative to `[cook]`.
+pub fn field_type(path: &str) -> Option<FieldType> {
+    FIELDS.iter().find(|(p, _)| *p == path).map(|(_, t)| *t)
+}
+
+/// A recipe field set from the environment or the command line.
+pub struct Override {
+    /// The keys of the field under `[cook]`.
+    keys: Vec<String>,
+    value: toml::Value,
+    /// Where the override comes from, `--set cook.hashes=md5` for example.
+    origin: String,
+}
+
+/// The prefix of the environment variables which override the recipe fields.
+const ENV_PREFIX: &str = "COOK_";
+
+impl Override {
+    /// Returns the override of the field, the path is a dotted one as
+    /// `cook.deploy.ssh.hostname` and the value is converted to the field type.
+    pub fn new(path: &str, value: &str, origin: String) -> Result<Override> {
+        let mut keys = validate::split_key(path);
+        if keys.len() < 2 || keys.remove(0) != COOK_KEY {
+            return Err(CookError::Config(format!(
+                "Unable to set {}: the field must be in the [cook] section.",
+                path
+            )));
+        }
+        let unsupported = || {
+            CookError::Config(format!(
+                "Unable to set {}: only the strings, the booleans, the integers and the lists of strings out of the arrays of tables, the profiles and the members can be set.",
+                path
+            ))
+        };
+        let mut field = String::new();
+        let mut kind = FieldType::Recipe;
+        for k in &keys {
+            if kind == FieldType::TableList || (kind == FieldType::Recipe && !field.is_empty()) {
+                return Err(unsupported());
+            }
+            if !field.is_empty() {
+                field.push('.');
+            }
+            field.push_str(if kind == FieldType::Map { "*" } else { k });
+            kind = field_type(&field)
+                .ok_or_else(|| CookError::Config(format!("Unknown field {}.", path)))?;
+        }
+        let value = match kind {
+            FieldType::String => toml::Value::String(value.to_owned()),
+            FieldType::Bool => toml::Value::Boolean(value.parse().map_err(|_| {
+                CookError::Config(format!(
+                    "Unable to set {}: \"{}\" is not true or false.",
+                    path, value
+                ))
+            })?),
+            FieldType::Integer => toml::Value::Integer(value.parse().map_err(|_| {
+                CookError::Config(format!(
+                    "Unable to set {}: \"{}\" is not an integer.",
+                    path, value
+                ))
+            })?),
+            FieldType::StringList => toml::Value::Array(
+                value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|v| !v.is_empty())
+                    .map(|v| toml::Value::String(v.to_owned()))
+                    .collect(),
+            ),
+            _ => return Err(unsupported()),
+        };
+        Ok(Override {
+            keys,
+            value,
+            origin,
+        })
+    }
+
+    /// Returns `true` if the field path, `cook.hashes[1]` for example, is
+    /// the overridden field or is in it.
+    pub fn sets(&self, path: &str) -> bool {
+        let keys: Vec<String> = self.keys.iter().map(|k| validate::quote_key(k)).collect();
+        let field = format!("{}.{}", COOK_KEY, keys.join("."));
+        path.strip_prefix(&field)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with(['.', '[']))
+    }
+
+    pub fn origin(&self) -> &str {
+        &self.origin
+    }
+
+    /// Returns the overrides of the `COOK_<SECTION>_<FIELD>` environment
+    /// variables, `COOK_DEPLOY_SSH_HOSTNAME` for example.
+    pub fn from_env() -> Result<Vec<Override>> {
+        let mut overrides = Vec::new();
+        for (name, value) in std::env::vars_os() {
+            // The other variables may be anything, even not UTF-8.
+            let name = match name.to_str() {
+                Some(n) if n.starts_with(ENV_PREFIX) => n,
+                _ => continue,
+            };
+            // The fields of the named tables and of the arrays of tables
+            // have no variables.
+            let field = FIELDS.iter().find(|(p, t)| {
+                !p.contains('*')
+                    && !p.starts_with("ingredient.")
+                    && !p.starts_with("artifact.")
+                    && matches!(
+                        t,
+                        FieldType::String
+                            | FieldType::Bool
+                            | FieldType::Integer
+                            | FieldType::StringList
+                    )
+                    && *p != EXTENDS_KEY
+                    && *p != INCLUDE_KEY
+                    && format!("{}{}", ENV_PREFIX, p.replace('.', "_").to_uppercase()) == name
+            });
+            if let Some((p, _)) = field {
+                let value = value.to_str().ok_or_else(|| {
+                    CookError::Config(format!("The {} variable is not valid UTF-8.", name))
+                })?;
+                overrides.push(Override::new(
+                    &format!("{}.{}", COOK_KEY, p),
+                    value,
+                    format!("the {} environment variable", name),
+                )?);
+            }
+        }
+        Ok(overrides)
+    }
+}
+
+/// Puts the overlay on top of the base value: tables are merged recursively,
+/// arrays of tables are extended and all the other values are replaced.
+pub fn merge(base: &mut toml::Value, overlay: &toml::Value) {
+    use toml::Value;
+
+    let is_table_array =
+        |a: &[Value]| !a.is_empty() && a.iter().all(|v| matches!(v, Value::Table(_)));
+
+    match (base, overlay) {
+        (Value::Table(b), Value::Table(o)) => {
+            for (k, v) in o {
+                match b.get_mut(k) {
+                    Some(bv) => merge(bv, v),
+                    None => {
+                        b.insert(k.clone(), v.clone());
+                    }
+                }
+            }
+        }
+        (Value::Array(b), Value::Array(o)) if is_table_array(o) => b.extend(o.iter().cloned()),
+        (b, o) => *b = o.clone(),
+    }
+}
+
+/// The unknown fields and the fields of a wrong type of the recipe files,
+/// with the files they are in.
+pub type FieldProblems = Vec<(String, Vec<validate::Diagnostic>)>;
+
+/// The recipe files met while resolving a recipe.
+#[derive(Default)]
+struct Loaded {
+    /// The files being loaded, to catch the cycles.
+    chain: Vec<PathBuf>,
+    /// All the files loaded so far, each of them is taken once.
+    taken: HashSet<PathBuf>,
+    /// The unknown fields and the fields of a wrong type of the files.
+    problems: FieldProblems,
+}
+
+/// Returns the `[cook]` table of the recipe file with everything it extends
+/// and includes, or an empty table if the file has already been taken.
+fn load_cook_table(path: &Path, loaded: &mut Loaded) -> Result<toml::Value> {
+    let canonical = path
+        .canonicalize()
+        .map_err(|e| CookError::Config(format!("Unable to read {}: {}", path.display(), e)))?;
+    if loaded.chain.contains(&canonical) {
+        let cycle: Vec<String> = loaded
+            .chain
+            .iter()
+            .chain(std::iter::once(&canonical))
+            .map(|p| p.display().to_string())
+            .collect();
+        return Err(CookError::Config(format!(
+            "The recipes include each other: {}",
+            cycle.join(" -> ")
+        )));
+    }
+    if !loaded.taken.insert(canonical.clone()) {
+        return Ok(toml::Value::Table(Default::default()));
+    }
+    let s = fs::read_to_string(&canonical)
+        .map_err(|e| CookError::Config(format!("Unable to read {}: {}", path.display(), e)))?;
+    let mut value: toml::Value = toml::from_str(&s)
+        .map_err(|e| CookError::Config(format!("Unable to parse {}: {}", path.display(), e)))?;
+    let mut cook = value
+        .as_table_mut()
+        .and_then(|t| t.remove(COOK_KEY))
+        .ok_or_else(|| CookError::Config(format!("{} has no [cook] section.", path.display())))?;
+    let problems = validate::fields(&mut cook, COOK_KEY);
+    if !problems.is_empty() {
+        loaded.problems.push((path.display().to_string(), problems));
+    }
+
+    loaded.chain.push(canonical.clone());
+    let cook = resolve_table(cook, canonical.parent().unwrap(), loaded);
+    loaded.chain.pop();
+    cook
+}
+
+fn resolve_table(
+    mut cook: toml::Value,
+    directory: &Path,
+    loaded: &mut Loaded,
+) -> Result<toml::Value> {
+    use toml::Value;
+
+    let table = match cook.as_table_mut() {
+        Some(t) => t,
+        None => return Ok(cook),
+    };
+    let mut files = Vec::new();
+    match table.remove(EXTENDS_KEY) {
+        Some(Value::String(f)) => files.push(f),
+        Some(_) => {
+            return Err(CookError::Config(
+                "The extends must be a file name.".to_owned(),
+            ))
+        }
+        None => {}
+    }
+    match table.remove(INCLUDE_KEY) {
+        Some(Value::Array(a)) => {
+            for f in a {
+                match f {
+                    Value::String(f) => files.push(f),
+                    _ => {
+                        return Err(CookError::Config(
+                            "The include must be a list of file names.".to_owned(),
+                        ))
+                    }
+                }
+            }
+        }
+        Some(_) => {
+            return Err(CookError::Config(
+                "The include must be a list of file names.".to_owned(),
+            ))
+        }
+        None => {}
+    }
+
+    let mut base = Value::Table(Default::default());
+    for f in files {
+        merge(&mut base, &load_cook_table(&directory.join(f), loaded)?);
+    }
+    merge(&mut base, &cook);
+    Ok(base)
+}
+
+/// Returns the `[cook]` table with the recipes it extends and includes put
+/// under it, their paths are relative to the directory.
+pub fn resolve(cook: toml::Value, directory: &Path) -> Result<toml::Value> {
+    let mut loaded = Loaded::default();
+    let cook = resolve_table(cook, directory, &mut loaded)?;
+    for (file_name, problems) in loaded.problems {
+        validate::report(problems, Some(&file_name))?;
+    }
+    Ok(cook)
+}
+
+/// Loads the recipe file with everything it extends and includes.
+pub fn load(file_name: &str) -> Result<CookConfig> {
+    let (config, problems) = load_checked(file_name)?;
+    for (file_name, problems) in problems {
+        validate::report(problems, Some(&file_name))?;
+    }
+    config
+}
+
+/// Loads the recipe file like `load`, but the fields of a wrong type are left
+/// out of the recipe instead of failing. The unknown fields and the fields of
+/// a wrong type are returned with the files they are in.
+pub fn load_checked(file_name: &str) -> Result<(Result<CookConfig>, FieldProblems)> {
+    let parse_error =
+        |e: toml::de::Error| CookError::Config(format!("Unable to parse {}: {}", file_name, e));
+    let s = fs::read_to_string(file_name)
+        .map_err(|e| CookError::Config(format!("Unable to read {}: {}", file_name, e)))?;
+    let mut value: toml::Value = toml::from_str(&s).map_err(parse_error)?;
+    let extends = value
+        .get(COOK_KEY)
+        .map(|c| c.get(EXTENDS_KEY).is_some() || c.get(INCLUDE_KEY).is_some())
+        .unwrap_or(false);
+    if !extends {
+        let problems = value
+            .get_mut(COOK_KEY)
+            .map(|cook| validate::fields(cook, COOK_KEY))
+            .unwrap_or_default();
+        if problems.is_empty() {
+            // Parsing the text keeps the positions in the errors.
+            return Ok((toml::from_str(&s).map_err(parse_error), Vec::new()));
+        }
+        let config = value.try_into().map_err(parse_error);
+        return Ok((config, vec![(file_name.to_owned(), problems)]));
+    }
+
+    let mut loaded = Loaded::default();
+    let mut config = toml::value::Table::new();
+    config.insert(
+        COOK_KEY.to_owned(),
+        load_cook_table(Path::new(file_name), &mut loaded)?,
+    );
+    let config = toml::Value::Table(config).try_into().map_err(parse_error);
+    Ok((config, loaded.problems))
+}
+
+impl Cook {
+    /// Returns the recipe with the overrides put on top of it, `what` tells
+    /// where the overrides come from.
+    fn overlay(&self, overlay: &toml::Value, what: &str) -> Result<Cook> {
+        let mut value = toml::Value::try_from(self)
+            .map_err(|e| CookError::Config(format!("Unable to serialize the recipe: {}", e)))?;
+        merge(&mut value, overlay);
+        value.try_into().map_err(|e| {
+            CookError::Config(format!("Unable to apply the overrides of {}: {}", what, e))
+        })
+    }
+
+    /// Returns the recipe with the fields set by the overrides in order.
+    pub fn overridden(&self, overrides: &[Override]) -> Result<Cook> {
+        if overrides.is_empty() {
+            return Ok(self.clone());
+        }
+        let mut overlay = toml::Value::Table(Default::default());
+        for o in overrides {
+            let mut value = o.value.clone();
+            for k in o.keys.iter().rev() {
+                let mut table = toml::value::Table::new();
+                table.insert(k.clone(), value);
+                value = toml::Value::Table(table);
+            }
+            merge(&mut overlay, &value);
+        }
+        self.overlay(&overlay, "the environment and the --set options")
+    }
+
+    /// Returns the recipe of the workspace member with its overrides applied.
+    pub fn member(&self, name: &str) -> Result<Cook> {
+        let mut base = self.clone();
+        base.member = None;
+        match self.member.as_ref().and_then(|m| m.get(name)) {
+            Some(o) => base.overlay(o, &format!("the \"{}\" member", name)),
+            None => Ok(base),
+        }
+    }
+
+    /// Returns the recipe with the overrides of the profiles applied in order.
+    pub fn profiles(&self, names: &[&str]) -> Result<Cook> {
+        let mut cook = self.clone();
+        cook.profile = None;
+        for name in names {
+            let overlay = self
+                .profile
+                .as_ref()
+                .and_then(|p| p.get(*name))
+                .ok_or_else(|| {
+                    CookError::Config(format!("The recipe has no \"{}\" profile.", name))
+                })?;
+            cook = cook.overlay(overlay, &format!("the \"{}\" profile", name))?;
+        }
+        Ok(cook)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(s: &str) -> toml::Value {
+        toml::from_str(s).unwrap()
+    }
+
+    /// Writes the recipes into a new directory and returns it.
+    fn recipes(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("cargo-cook-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        for (file, contents) in files {
+            fs::write(directory.join(file), contents).unwrap();
+        }
+        directory
+    }
+
+    #[test]
+    fn merge_tables() {
+        let mut base = value(
+            r#"
+            containers = ["tar"]
+            cook_directory = "cooked"
+            [build]
+            profile = "release"
+            features = ["a"]
+            [[ingredient]]
+            source = "src"
+            destination = "src"
+            "#,
+        );
+        merge(
+            &mut base,
+            &value(
+                r#"
+                containers = ["tar.bzip2"]
+                [build]
+                features = ["b"]
+                [[ingredient]]
+                source = "README.md"
+                destination = "README.md"
+                "#,
+            ),
+        );
+        assert_eq!(base["containers"], value(r#"a = ["tar.bzip2"]"#)["a"]);
+        assert_eq!(base["cook_directory"].as_str(), Some("cooked"));
+        assert_eq!(base["build"]["profile"].as_str(), Some("release"));
+        assert_eq!(base["build"]["features"], value(r#"a = ["b"]"#)["a"]);
+        let sources: Vec<_> = base["ingredient"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|i| i["source"].as_str().unwrap())
+            .collect();
+        assert_eq!(sources, ["src", "README.md"]);
+    }
+
+    #[test]
+    fn extends_and_includes() {
+        let directory = recipes(
+            "extends",
+            &[
+                (
+                    "base.toml",
+                    "[cook]\ncontainers = [\"tar\"]\nhashes = [\"md5\"]\ncook_directory = \"base\"\n",
+                ),
+                ("deploy.toml", "[cook]\nhashes = [\"sha512\"]\n"),
+                (
+                    "Cook.toml",
+                    "[cook]\nextends = \"base.toml\"\ninclude = [\"deploy.toml\"]\ncook_directory = \"cooked\"\n",
+                ),
+            ],
+        );
+        let c = load(directory.join("Cook.toml").to_str().unwrap()).unwrap();
+        assert_eq!(c.cook.containers, ["tar"]);
+        assert_eq!(c.cook.hashes, Some(vec!["sha512".to_owned()]));
+        assert_eq!(c.cook.cook_directory, "cooked");
+        let _ = fs::remove_dir_all(directory);
+    }
+
+    #[test]
+    fn include_cycles() {
+        let directory = recipes(
+            "cycles",
+            &[
+                (
+                    "self.toml",
+                    "[cook]\ninclude = [\"self.toml\"]\ncontainers = [\"tar\"]\ncook_directory = \"c\"\n",
+                ),
+                (
+                    "a.toml",
+                    "[cook]\nextends = \"b.toml\"\ncontainers = [\"tar\"]\ncook_directory = \"c\"\n",
+                ),
+                ("b.toml", "[cook]\nextends = \"a.toml\"\n"),
+            ],
+        );
+        for file in &["self.toml", "a.toml"] {
+            match load(directory.join(file).to_str().unwrap()) {
+                Err(CookError::Config(e)) => {
+                    assert!(e.starts_with("The recipes include each other: "), "{}", e)
+                }
+                r => panic!("{} is loaded: {:?}", file, r),
+            }
+        }
+        let _ = fs::remove_dir_all(directory);
+    }
+
+    #[test]
+    fn include_diamond() {
+        let directory = recipes(
+            "diamond",
+            &[
+                (
+                    "d.toml",
+                    "[cook]\ncontainers = [\"tar\"]\ncook_directory = \"c\"\n\
+                     [[cook.ingredient]]\nsource = \"LICENSE\"\ndestination = \"LICENSE\"\n",
+                ),
+                ("b.toml", "[cook]\nextends = \"d.toml\"\n"),
+                ("c.toml", "[cook]\nextends = \"d.toml\"\n"),
+                ("Cook.toml", "[cook]\ninclude = [\"b.toml\", \"c.toml\"]\n"),
+            ],
+        );
+        let c = load(directory.join("Cook.toml").to_str().unwrap()).unwrap();
+        assert_eq!(c.cook.ingredient.unwrap().len(), 1);
+        let _ = fs::remove_dir_all(directory);
+    }
+
+    fn set(path: &str, value: &str) -> Result<Override> {
+        Override::new(path, value, format!("--set {}={}", path, value))
+    }
+
+    fn error(path: &str, value: &str) -> String {
+        match set(path, value) {
+            Err(CookError::Config(e)) => e,
+            _ => panic!("{} is set to {}", path, value),
+        }
+    }
+
+    #[test]
+    fn override_types() {
+        let cook: Cook =
+            toml::from_str("containers = [\"tar\"]\ncook_directory = \"cooked\"").unwrap();
+        let cook = cook
+            .overridden(&[
+                set("cook.cook_directory", "/tmp/cooked").unwrap(),
+                set("cook.reproducible", "true").unwrap(),
+                set("cook.hashes", "md5, sha256,").unwrap(),
+                set(r#"cook.container."tar.gz".level"#, "9").unwrap(),
+                set("cook.build.features", "").unwrap(),
+            ])
+            .unwrap();
+        assert_eq!(cook.cook_directory, "/tmp/cooked");
+        assert_eq!(cook.reproducible, Some(true));
+        assert_eq!(
+            cook.hashes,
+            Some(vec!["md5".to_owned(), "sha256".to_owned()])
+        );
+        assert_eq!(cook.container.unwrap()["tar.gz"].level, Some(9));
+        assert_eq!(cook.build.unwrap().features, Some(Vec::new()));
+
+        assert_eq!(
+            error("cook.reproducible", "yes"),
+            "Unable to set cook.reproducible: \"yes\" is not true or false."
+        );
+        assert_eq!(
+            error(r#"cook.container."tar.gz".level"#, "best"),
+            r#"Unable to set cook.container."tar.gz".level: "best" is not an integer."#
+        );
+    }
+
+    #[test]
+    fn override_fields() {
+        assert_eq!(
+            error("cook.cook_dir", "cooked"),
+            "Unknown field cook.cook_dir."
+        );
+        assert_eq!(
+            error("cook.container.tar.gz.level", "9"),
+            "Unknown field cook.container.tar.gz.level."
+        );
+        assert!(error("cook_directory", "cooked").contains("must be in the [cook] section"));
+        for path in &[
+            "cook.member.app.cook_directory",
+            "cook.profile.ci.hashes",
+            "cook.ingredient.source",
+            "cook.build",
+        ] {
+            assert!(error(path, "x").starts_with(&format!("Unable to set {}: only", path)));
+        }
+
+        let o = set(r#"cook.container."tar.gz".level"#, "9").unwrap();
+        assert!(o.sets(r#"cook.container."tar.gz".level"#));
+        assert!(!o.sets(r#"cook.container."tar.gz""#));
+        let o = set("cook.hashes", "md5").unwrap();
+        assert!(o.sets("cook.hashes[0]"));
+        assert!(!o.sets("cook.hashes_type"));
+        assert_eq!(o.origin(), "--set cook.hashes=md5");
+    }
+
+    #[test]
+    fn override_from_env() {
+        std::env::set_var("COOK_COOK_DIRECTORY", "/tmp/cooked");
+        std::env::set_var("COOK_BUILD_PROFILE", "dev");
+        std::env::set_var("COOK_INGREDIENT_SOURCE", "src");
+        let overrides = Override::from_env();
+        std::env::remove_var("COOK_COOK_DIRECTORY");
+        std::env::remove_var("COOK_BUILD_PROFILE");
+        std::env::remove_var("COOK_INGREDIENT_SOURCE");
+
+        let mut origins: Vec<_> = overrides
+            .unwrap()
+            .iter()
+            .map(|o| o.origin().to_owned())
+            .collect();
+        origins.sort();
+        assert_eq!(
+            origins,
+            [
+                "the COOK_BUILD_PROFILE environment variable",
+                "the COOK_COOK_DIRECTORY environment variable"
+            ]
+        );
+
+        // Only the overrides have to be UTF-8.
+        #[cfg(unix)]
+        {
+            use std::ffi::OsStr;
+            use std::os::unix::ffi::OsStrExt;
+
+            let raw = OsStr::from_bytes(b"\xff");
+            std::env::set_var("CARGO_COOK_TEST_RAW", raw);
+            let overrides = Override::from_env();
+            std::env::set_var("COOK_TARGET_RENAME", raw);
+            let wrong = Override::from_env();
+            std::env::remove_var("CARGO_COOK_TEST_RAW");
+            std::env::remove_var("COOK_TARGET_RENAME");
+
+            assert!(overrides.is_ok());
+            match wrong {
+                Err(CookError::Config(m)) => assert!(m.contains("COOK_TARGET_RENAME")),
+                r => panic!("The variable is taken: {:?}", r.map(|o| o.len())),
+            }
+        }
+    }
 }
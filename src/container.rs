@@ -1,68 +1,320 @@
+use crate::error::{CookError, Result};
+use crate::hash::HashingWriter;
 use std::collections::HashMap;
+use std::io::{BufWriter, Read, Write};
 
 /// A file name and its' content as string.
 pub type File = (String, String);
 pub type Files = Vec<(String, String)>;
+/// Packs the files into the writer of the destination file, the path of
+/// which is for the errors.
+type ContainerFn = fn(&mut dyn Write, &str, &[File], &Options) -> Result<()>;
+/// Returns the tar stream of the container file.
+type ReaderFn = fn(std::fs::File) -> Box<dyn Read>;
+
+/// How the containers are written.
+#[derive(Default, Debug, Clone, Copy)]
+pub struct Options {
+    /// The same files give the byte-identical containers: the entries are
+    /// sorted and have no time, owner or permissions of the files on disk.
+    pub reproducible: bool,
+    /// The compression level of the compressing containers, their default
+    /// one if there is none.
+    #[cfg_attr(not(any(feature = "bzip2", feature = "gzip")), allow(dead_code))]
+    pub level: Option<u32>,
+}
 
 lazy_static::lazy_static! {
-    static ref CONTAINERS: HashMap<&'static str, fn(&str, &[File])> = {
+    static ref CONTAINERS: HashMap<&'static str, (ContainerFn, ReaderFn)> = {
         let mut m = HashMap::new();
-        m.insert("tar", tar as fn(&str, &[File]));
+        m.insert("tar", (tar as ContainerFn, tar_reader as ReaderFn));
         #[cfg(feature = "bzip2")]
-        m.insert("tar.bzip2", bzip2 as fn(&str, &[File]));
+        m.insert("tar.bzip2", (bzip2 as ContainerFn, bzip2_reader as ReaderFn));
+        #[cfg(feature = "gzip")]
+        m.insert("tar.gz", (gzip as ContainerFn, gzip_reader as ReaderFn));
+        #[cfg(feature = "gzip")]
+        m.insert("tgz", (gzip as ContainerFn, gzip_reader as ReaderFn));
         m
     };
 }
 
 #[cfg(feature = "bzip2")]
-fn bzip2(destination_file_path: &str, files: &[File]) {
+fn bzip2_reader(file: std::fs::File) -> Box<dyn Read> {
+    Box::new(bzip2::read::BzDecoder::new(file))
+}
+
+#[cfg(feature = "bzip2")]
+fn bzip2(
+    out: &mut dyn Write,
+    destination_file_path: &str,
+    files: &[File],
+    options: &Options,
+) -> Result<()> {
     use crate::term_print::*;
-    use bzip2::read::BzEncoder;
+    use bzip2::write::BzEncoder;
     use bzip2::Compression;
-    use std::fs::File;
-    use std::io::{Read, Write};
 
-    const TEMP_FILE: &str = "/tmp/cooked.tar";
     const BZIP2_LABEL: &str = "[bzip2]";
 
-    tar(TEMP_FILE, files);
-    let mut tar_file = File::open(TEMP_FILE).unwrap();
-    let mut raw_bytes = Vec::new();
-    tar_file.read_to_end(&mut raw_bytes).unwrap();
-    let mut compressed_bytes = Vec::new();
-    let mut compressor = BzEncoder::new(raw_bytes.as_slice(), Compression::best());
-    compressor.read_to_end(&mut compressed_bytes).unwrap();
-    let ratio = 100f32 / (raw_bytes.len() as f32 / compressed_bytes.len() as f32);
-    let mut compressed_archive = File::create(destination_file_path).unwrap();
+    let write_error = |e: std::io::Error| {
+        CookError::Container(format!("Unable to write {}: {}", destination_file_path, e))
+    };
+    let level = options
+        .level
+        .map(Compression::new)
+        .unwrap_or_else(Compression::best);
+    let compressor = BzEncoder::new(out, level);
+    let mut compressor = write_tar(compressor, destination_file_path, files, options)?;
+    compressor.try_finish().map_err(write_error)?;
+    let ratio = 100f32 / (compressor.total_in() as f32 / compressor.total_out() as f32);
     term_println(
         term::color::WHITE,
         BZIP2_LABEL,
         &format!("Compressed ratio: {:.2}%", ratio),
     );
-    compressed_archive
-        .write_all(compressed_bytes.as_slice())
-        .unwrap();
+    compressor.finish().map(|_| ()).map_err(write_error)
+}
+
+#[cfg(feature = "gzip")]
+fn gzip_reader(file: std::fs::File) -> Box<dyn Read> {
+    Box::new(flate2::read::GzDecoder::new(file))
+}
+
+#[cfg(feature = "gzip")]
+fn gzip(
+    out: &mut dyn Write,
+    destination_file_path: &str,
+    files: &[File],
+    options: &Options,
+) -> Result<()> {
+    use flate2::{Compression, GzBuilder};
+
+    let level = options.level.map(Compression::new).unwrap_or_default();
+    // No file name and no time in the header, the same data is compressed
+    // into the same bytes.
+    let compressor = GzBuilder::new().mtime(0).write(out, level);
+    write_tar(compressor, destination_file_path, files, options)?
+        .finish()
+        .map(|_| ())
+        .map_err(|e| {
+            CookError::Container(format!("Unable to write {}: {}", destination_file_path, e))
+        })
 }
 
-fn tar(destination_file_path: &str, files: &[File]) {
-    use std::fs::File;
+/// Returns the header of the file entry which depends on nothing but the
+/// size of the file and whether it is executable.
+fn reproducible_header(source: &std::fs::File, mtime: u64) -> std::io::Result<tar::Header> {
+    let metadata = source.metadata()?;
+    #[cfg(unix)]
+    let executable = {
+        use std::os::unix::fs::PermissionsExt;
+        metadata.permissions().mode() & 0o111 != 0
+    };
+    #[cfg(not(unix))]
+    let executable = false;
+
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Regular);
+    header.set_size(metadata.len());
+    header.set_mode(if executable { 0o755 } else { 0o644 });
+    header.set_mtime(mtime);
+    header.set_uid(0);
+    header.set_gid(0);
+    header.set_username("")?;
+    header.set_groupname("")?;
+    Ok(header)
+}
+
+fn tar_reader(file: std::fs::File) -> Box<dyn Read> {
+    Box::new(file)
+}
+
+/// Writes the files as a tar stream into the writer of the container file
+/// and returns the writer.
+fn write_tar<W: Write>(
+    out: W,
+    destination_file_path: &str,
+    files: &[File],
+    options: &Options,
+) -> Result<W> {
     use tar::Builder;
 
-    let file = File::create(destination_file_path).unwrap();
-    let mut ar = Builder::new(file);
+    let write_error = |e: std::io::Error| {
+        CookError::Container(format!("Unable to write {}: {}", destination_file_path, e))
+    };
+    let mut files: Vec<&(String, String)> = files.iter().collect();
+    // Without the time of the reproducible build the epoch is as good as any.
+    let mtime = crate::interpolate::source_date_epoch().unwrap_or(0);
+    if options.reproducible {
+        files.sort();
+    }
+
+    let mut ar = Builder::new(out);
     for f in files {
-        if let Ok(mut dest_file) = File::open(&f.1) {
-            ar.append_file(&f.0, &mut dest_file).unwrap();
+        let open_error =
+            |e: std::io::Error| CookError::Ingredient(format!("Unable to open {}: {}", f.1, e));
+        let mut source = std::fs::File::open(&f.1).map_err(open_error)?;
+        if options.reproducible {
+            let mut header = reproducible_header(&source, mtime).map_err(open_error)?;
+            ar.append_data(&mut header, &f.0, &mut source)
+                .map_err(write_error)?;
         } else {
-            panic!("No such file or directory: {}", f.1);
+            ar.append_file(&f.0, &mut source).map_err(write_error)?;
         }
     }
+    ar.into_inner().map_err(write_error)
+}
+
+fn tar(
+    out: &mut dyn Write,
+    destination_file_path: &str,
+    files: &[File],
+    options: &Options,
+) -> Result<()> {
+    write_tar(out, destination_file_path, files, options).map(|_| ())
 }
 
 pub fn support_container(container: &str) -> bool {
     CONTAINERS.get::<str>(container).is_some()
 }
 
-pub fn compress(files: &[File], destination_file_path: &str, container: &str) {
-    CONTAINERS.get::<str>(container).unwrap()(destination_file_path, files)
+/// Returns the compression levels of the container type, `None` if it
+/// doesn't compress.
+pub fn levels(container: &str) -> Option<(u32, u32)> {
+    match container {
+        "tar.bzip2" => Some((1, 9)),
+        "tar.gz" | "tgz" => Some((0, 9)),
+        _ => None,
+    }
+}
+
+/// Packs the files into the destination file and returns the hashes of the
+/// hash types, which are computed while the file is being written.
+pub fn compress(
+    files: &[File],
+    destination_file_path: &str,
+    container: &str,
+    options: &Options,
+    hash_types: &[String],
+) -> Result<Vec<(String, String)>> {
+    let (f, _) = CONTAINERS.get::<str>(container).ok_or_else(|| {
+        CookError::Container(format!(
+            "The \"{}\" container type is unsupported.",
+            container
+        ))
+    })?;
+    let write_error = |e: std::io::Error| {
+        CookError::Container(format!("Unable to write {}: {}", destination_file_path, e))
+    };
+    let file = std::fs::File::create(destination_file_path).map_err(write_error)?;
+    let mut out = HashingWriter::new(BufWriter::new(file), hash_types)?;
+    f(&mut out, destination_file_path, files, options)?;
+    let (mut file, hashes) = out.finish();
+    file.flush().map_err(write_error)?;
+    Ok(hashes)
+}
+
+/// Opens the container file as a tar archive to read the entries of.
+pub fn open(path: &str, container: &str) -> Result<tar::Archive<Box<dyn Read>>> {
+    let (_, reader) = CONTAINERS.get::<str>(container).ok_or_else(|| {
+        CookError::Container(format!(
+            "The \"{}\" container type is unsupported.",
+            container
+        ))
+    })?;
+    let file = std::fs::File::open(path)
+        .map_err(|e| CookError::Container(format!("Unable to open {}: {}", path, e)))?;
+    Ok(tar::Archive::new(reader(file)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::time::{Duration, SystemTime};
+
+    /// Writes the same files into a temporary directory, the time they are
+    /// modified at is the one given.
+    fn ingredients(name: &str, modified: SystemTime) -> (PathBuf, Files) {
+        let directory =
+            std::env::temp_dir().join(format!("cargo-cook-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        let mut files = Vec::new();
+        for (file, contents) in &[("README.md", "# App\n"), ("app", "#!/bin/sh\n")] {
+            let path = directory.join(file);
+            fs::write(&path, contents).unwrap();
+            fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(modified)
+                .unwrap();
+            files.push((file.to_string(), path.to_str().unwrap().to_owned()));
+        }
+        (directory, files)
+    }
+
+    #[test]
+    fn reproducible_tar() {
+        let options = Options {
+            reproducible: true,
+            level: None,
+        };
+        let (first, first_files) = ingredients("tar-first", SystemTime::UNIX_EPOCH);
+        let (second, mut second_files) = ingredients(
+            "tar-second",
+            SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000),
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            let mode = |f: &File, m| fs::set_permissions(&f.1, fs::Permissions::from_mode(m));
+            mode(&first_files[0], 0o600).unwrap();
+            mode(&second_files[0], 0o664).unwrap();
+            mode(&first_files[1], 0o700).unwrap();
+            mode(&second_files[1], 0o775).unwrap();
+        }
+        // The order of the files doesn't matter either.
+        second_files.reverse();
+
+        let first_tar = write_tar(Vec::new(), "first.tar", &first_files, &options).unwrap();
+        let second_tar = write_tar(Vec::new(), "second.tar", &second_files, &options).unwrap();
+        assert!(!first_tar.is_empty());
+        assert!(first_tar == second_tar);
+        let _ = fs::remove_dir_all(first);
+        let _ = fs::remove_dir_all(second);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn gzip_round_trip() {
+        let (directory, files) = ingredients("gzip", SystemTime::UNIX_EPOCH);
+        let archive = directory.join("app.tar.gz");
+        let archive = archive.to_str().unwrap();
+        let options = Options {
+            reproducible: true,
+            level: Some(9),
+        };
+        compress(&files, archive, "tar.gz", &options, &[]).unwrap();
+
+        let mut entries = Vec::new();
+        for entry in open(archive, "tar.gz").unwrap().entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let mut contents = String::new();
+            entry.read_to_string(&mut contents).unwrap();
+            let name = entry.path().unwrap().to_str().unwrap().to_owned();
+            entries.push((name, contents));
+        }
+        assert_eq!(
+            entries,
+            [
+                ("README.md".to_owned(), "# App\n".to_owned()),
+                ("app".to_owned(), "#!/bin/sh\n".to_owned()),
+            ]
+        );
+        let _ = fs::remove_dir_all(directory);
+    }
 }
@@ -0,0 +1,114 @@
+use crate::error::{path_str, CookError, Result};
+use std::collections::{BTreeSet, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const PATCHELF: &str = "patchelf";
+
+/// Returns the `DT_NEEDED` entries of the ELF file, nothing if it is not an ELF.
+fn needed_libraries(path: &Path) -> Result<Vec<String>> {
+    let bytes = fs::read(path)
+        .map_err(|e| CookError::Ingredient(format!("Unable to read {}: {}", path.display(), e)))?;
+    Ok(match goblin::elf::Elf::parse(&bytes) {
+        Ok(elf) => elf.libraries.iter().map(|l| (*l).to_owned()).collect(),
+        Err(_) => Vec::new(),
+    })
+}
+
+fn is_elf(path: &Path) -> bool {
+    fs::read(path)
+        .map(|b| goblin::elf::Elf::parse(&b).is_ok())
+        .unwrap_or(false)
+}
+
+/// Returns the directories to look for the shared libraries in: the build
+/// tree of the artifacts first and then the configured library paths.
+pub fn search_paths(artifact_directory: &str, library_paths: &[String]) -> Vec<PathBuf> {
+    let root = Path::new(artifact_directory);
+    let mut paths = vec![root.to_path_buf(), root.join("deps")];
+    // Build scripts of the `-sys` crates put the libraries they build there.
+    if let Ok(dir) = fs::read_dir(root.join("build")) {
+        let mut outs: Vec<PathBuf> = dir
+            .filter_map(|e| e.ok())
+            .map(|e| e.path().join("out"))
+            .filter(|p| p.is_dir())
+            .collect();
+        outs.sort();
+        for out in outs {
+            paths.push(out.join("lib"));
+            paths.push(out);
+        }
+    }
+    paths.extend(library_paths.iter().map(PathBuf::from));
+    paths
+}
+
+/// Returns the shared libraries the artifacts depend on, directly or not.
+///
+/// Only the libraries found in the search paths are taken, everything else
+/// is considered to be a part of the system.
+pub fn collect(artifacts: &[String], search_paths: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut libraries = Vec::new();
+    let mut seen = BTreeSet::new();
+    let mut queue: VecDeque<PathBuf> = artifacts.iter().map(PathBuf::from).collect();
+
+    while let Some(file) = queue.pop_front() {
+        for name in needed_libraries(&file)? {
+            if !seen.insert(name.clone()) {
+                continue;
+            }
+            if let Some(path) = search_paths
+                .iter()
+                .map(|p| p.join(&name))
+                .find(|p| p.is_file())
+            {
+                libraries.push(path.clone());
+                queue.push_back(path);
+            }
+        }
+    }
+    Ok(libraries)
+}
+
+/// Copies the ELF artifact into the staging directory and sets its RPATH.
+///
+/// Returns the path of the copy, or `None` if the artifact is not an ELF.
+pub fn rewrite_rpath(
+    artifact: &str,
+    rpath: &str,
+    staging_directory: &Path,
+) -> Result<Option<String>> {
+    let source = Path::new(artifact);
+    if !is_elf(source) {
+        return Ok(None);
+    }
+
+    let copy = staging_directory.join(source.file_name().unwrap());
+    fs::create_dir_all(staging_directory).map_err(|e| {
+        CookError::Ingredient(format!(
+            "Unable to create {}: {}",
+            staging_directory.display(),
+            e
+        ))
+    })?;
+    fs::copy(source, &copy).map_err(|e| {
+        CookError::Ingredient(format!("Unable to copy {}: {}", source.display(), e))
+    })?;
+
+    // DT_RPATH, unlike DT_RUNPATH, is also used for the indirect dependencies.
+    let status = Command::new(PATCHELF)
+        .arg("--force-rpath")
+        .arg("--set-rpath")
+        .arg(rpath)
+        .arg(&copy)
+        .status()
+        .map_err(|e| CookError::Ingredient(format!("Unable to run {}: {}", PATCHELF, e)))?;
+    if !status.success() {
+        return Err(CookError::Ingredient(format!(
+            "Unable to set the RPATH of {}.",
+            artifact
+        )));
+    }
+    Ok(Some(path_str(&copy)?.to_owned()))
+}
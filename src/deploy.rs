@@ -4,51 +4,65 @@ use std::result::Result as StdResult;
 use sysconf::{sysconf, SysconfVariable};
 
 pub type Result = StdResult<(), String>;
+/// Deploys the files.
+type DeployFn = fn(&[String], &Deploy) -> Result;
+/// Describes what deploying the files would do.
+type PlanFn = fn(&[String], &Deploy) -> Vec<String>;
 
 const BYTES_IN_MB: f64 = 1_048_576.0;
 
 lazy_static::lazy_static! {
-    static ref TARGETS: HashMap<&'static str, fn(&str, &Deploy) -> Result> = {
+    static ref TARGETS: HashMap<&'static str, (DeployFn, PlanFn)> = {
         let mut m = HashMap::new();
         #[cfg(feature = "ssh")]
-        m.insert("ssh", ssh as fn(&str, &Deploy) -> Result);
-        m.insert("fscopy", fscopy as fn(&str, &Deploy) -> Result);
+        m.insert("ssh", (ssh as DeployFn, ssh_plan as PlanFn));
+        m.insert("fscopy", (fscopy as DeployFn, fscopy_plan as PlanFn));
         m
     };
     static ref PAGE_SIZE: i64 = sysconf(SysconfVariable::ScPagesize).unwrap() as i64;
 }
 
-fn fscopy(source: &str, d: &Deploy) -> Result {
+/// Returns the name of the file without the directories.
+fn file_name(path: &str) -> &str {
+    std::path::Path::new(path)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or(path)
+}
+
+fn fscopy_plan(files: &[String], d: &Deploy) -> Vec<String> {
+    match d.fscopy {
+        Some(ref fscopy) => files
+            .iter()
+            .map(|f| format!("Copy \"{}\" to \"{}/{}\"", f, fscopy.path, file_name(f)))
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+fn fscopy(files: &[String], d: &Deploy) -> Result {
     use crate::term_print::*;
     use std::fs;
-    use std::path::Path;
 
     const FSCOPY_LABEL: &str = "[fscopy]";
 
     if let Some(ref fscopy) = d.fscopy {
-        let path = Path::new(source);
-        let dir = fs::read_dir(path).unwrap();
-        for entry in dir {
-            let e = entry.unwrap();
-            let entry_path = e.path();
-            let path = entry_path.to_str().unwrap();
-            if let Some(file_name) = e.file_name().to_str() {
-                term_rprint(
-                    term::color::WHITE,
-                    FSCOPY_LABEL,
-                    &format!("Copying \"{}\" to \"{}\"", path, fscopy.path),
-                );
-                if let Err(err) = fs::copy(e.path(), &format!("{}/{}", fscopy.path, file_name)) {
-                    term_rprint_finish();
-                    return Err(err.to_string());
-                }
-                term_rprint(
-                    term::color::WHITE,
-                    FSCOPY_LABEL,
-                    &format!("Copied \"{}\" to \"{}\"", path, fscopy.path),
-                );
+        for path in files {
+            term_rprint(
+                term::color::WHITE,
+                FSCOPY_LABEL,
+                &format!("Copying \"{}\" to \"{}\"", path, fscopy.path),
+            );
+            if let Err(err) = fs::copy(path, format!("{}/{}", fscopy.path, file_name(path))) {
                 term_rprint_finish();
+                return Err(err.to_string());
             }
+            term_rprint(
+                term::color::WHITE,
+                FSCOPY_LABEL,
+                &format!("Copied \"{}\" to \"{}\"", path, fscopy.path),
+            );
+            term_rprint_finish();
         }
     }
 
@@ -56,11 +70,31 @@ fn fscopy(source: &str, d: &Deploy) -> Result {
 }
 
 #[cfg(feature = "ssh")]
-fn ssh(source: &str, d: &Deploy) -> Result {
+fn ssh_plan(files: &[String], d: &Deploy) -> Vec<String> {
+    let ssh = match d.ssh {
+        Some(ref ssh) => ssh,
+        None => return Vec::new(),
+    };
+    let destination = format!("{}@{}:{}", ssh.username, ssh.hostname, ssh.remote_path);
+    let mut plan: Vec<String> = files
+        .iter()
+        .map(|f| format!("Upload \"{}\" to \"{}/{}\"", f, destination, file_name(f)))
+        .collect();
+    if let Some(ref ds) = ssh.deploy_script {
+        plan.push(format!(
+            "Upload and execute \"{}\" in \"{}\"",
+            ds, destination
+        ));
+    }
+    plan
+}
+
+#[cfg(feature = "ssh")]
+fn ssh(files: &[String], d: &Deploy) -> Result {
     use crate::term_print::*;
     use rpassword::read_password;
     use ssh2::Session;
-    use std::fs::{self, File};
+    use std::fs::File;
     use std::io::{Read, Write};
     use std::net::TcpStream;
     use std::os::unix::fs::PermissionsExt;
@@ -93,40 +127,44 @@ fn ssh(source: &str, d: &Deploy) -> Result {
         }
     };
 
-    let send_file = |sess: &Session, local_path: &Path, remote_path: &Path| {
+    let send_file = |sess: &Session, local_path: &Path, remote_path: &Path| -> Result {
+        let file_path_str = local_path.display().to_string();
+        let read_error = |e: std::io::Error| format!("Unable to read {}: {}", file_path_str, e);
         let mut buffer = vec![0; *PAGE_SIZE as usize];
         let mut read = 0u64;
-        if let Ok(mut file) = File::open(local_path) {
-            let metadata = file.metadata().unwrap();
-            let file_size = metadata.len();
-            let file_path_str = local_path.to_str().unwrap();
-            let mut remote_file = sess
-                .scp_send(
-                    remote_path,
-                    metadata.permissions().mode() as i32,
-                    file_size,
-                    None,
-                )
-                .unwrap();
-            while let Ok(read_bytes) = file.read(&mut buffer) {
-                if read_bytes == 0usize {
-                    break;
-                }
-                read += read_bytes as u64;
-                remote_file.write_all(&buffer).unwrap();
-                term_rprint(
-                    term::color::WHITE,
-                    SSH_LABEL,
-                    &format!(
-                        "Sending \"{}\" [{:.2} MB of {:.2} MB]",
-                        file_path_str,
-                        read as f64 / BYTES_IN_MB,
-                        file_size as f64 / BYTES_IN_MB
-                    ),
-                );
+        let mut file = File::open(local_path).map_err(read_error)?;
+        let metadata = file.metadata().map_err(read_error)?;
+        let file_size = metadata.len();
+        let mut remote_file = sess
+            .scp_send(
+                remote_path,
+                metadata.permissions().mode() as i32,
+                file_size,
+                None,
+            )
+            .map_err(|e| format!("Unable to upload {}: {}", file_path_str, e))?;
+        loop {
+            let read_bytes = file.read(&mut buffer).map_err(read_error)?;
+            if read_bytes == 0usize {
+                break;
             }
-            term_rprint_finish();
+            read += read_bytes as u64;
+            remote_file
+                .write_all(&buffer[..read_bytes])
+                .map_err(|e| format!("Unable to upload {}: {}", file_path_str, e))?;
+            term_rprint(
+                term::color::WHITE,
+                SSH_LABEL,
+                &format!(
+                    "Sending \"{}\" [{:.2} MB of {:.2} MB]",
+                    file_path_str,
+                    read as f64 / BYTES_IN_MB,
+                    file_size as f64 / BYTES_IN_MB
+                ),
+            );
         }
+        term_rprint_finish();
+        Ok(())
     };
 
     if let Some(ref ssh) = d.ssh {
@@ -135,10 +173,12 @@ fn ssh(source: &str, d: &Deploy) -> Result {
             SSH_LABEL,
             &format!("Connecting to {}", ssh.hostname),
         );
-        let tcp = TcpStream::connect(&ssh.hostname).unwrap();
-        let mut sess = Session::new().unwrap();
+        let tcp = TcpStream::connect(&ssh.hostname)
+            .map_err(|e| format!("Unable to connect to {}: {}", ssh.hostname, e))?;
+        let mut sess = Session::new().map_err(|e| e.to_string())?;
         sess.set_tcp_stream(tcp);
-        sess.handshake().unwrap();
+        sess.handshake()
+            .map_err(|e| format!("Unable to connect to {}: {}", ssh.hostname, e))?;
 
         for i in 0..3 {
             term_print(
@@ -146,7 +186,8 @@ fn ssh(source: &str, d: &Deploy) -> Result {
                 SSH_LABEL,
                 &format!("Password for {}: ", ssh.username),
             );
-            let ssh_password = read_password().unwrap();
+            let ssh_password =
+                read_password().map_err(|e| format!("Unable to read the password: {}", e))?;
 
             if ssh_password.is_empty() {
                 if i == 2 {
@@ -172,15 +213,11 @@ fn ssh(source: &str, d: &Deploy) -> Result {
 
         term_println(term::color::WHITE, SSH_LABEL, "Uploading files...");
 
-        let path = Path::new(source);
         exec(&sess, &format!("mkdir -p {}", ssh.remote_path));
-        let dir = fs::read_dir(path).unwrap();
-        for entry in dir {
-            let e = entry.unwrap();
-            let file_name_str = e.file_name().into_string().unwrap();
-            let remote_path_str = format!("{}/{}", ssh.remote_path, file_name_str);
+        for f in files {
+            let remote_path_str = format!("{}/{}", ssh.remote_path, file_name(f));
             let remote_path = Path::new(&remote_path_str);
-            send_file(&sess, &e.path(), &remote_path);
+            send_file(&sess, Path::new(f), remote_path)?;
         }
 
         if let Some(ref ds) = ssh.deploy_script {
@@ -194,7 +231,7 @@ fn ssh(source: &str, d: &Deploy) -> Result {
             let local_path = Path::new(ds);
             let remote_path = Path::new(&remote_path_str);
 
-            send_file(&sess, &local_path, &remote_path);
+            send_file(&sess, local_path, remote_path)?;
 
             term_println(
                 term::color::WHITE,
@@ -216,6 +253,16 @@ pub fn support_deploy_target(target: &str) -> bool {
     TARGETS.get::<str>(&target.to_lowercase()).is_some()
 }
 
-pub fn deploy(target: &str, cook_dir: &str, d: &Deploy) -> Result {
-    TARGETS.get::<str>(&target.to_lowercase()).unwrap()(cook_dir, d)
+pub fn deploy(target: &str, files: &[String], d: &Deploy) -> Result {
+    match TARGETS.get::<str>(&target.to_lowercase()) {
+        Some(t) => t.0(files, d),
+        None => Err(format!("The \"{}\" deploy target is unsupported.", target)),
+    }
+}
+
+pub fn plan(target: &str, files: &[String], d: &Deploy) -> Vec<String> {
+    TARGETS
+        .get::<str>(&target.to_lowercase())
+        .map(|t| t.1(files, d))
+        .unwrap_or_default()
 }
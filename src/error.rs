@@ -0,0 +1,71 @@
+use std::fmt;
+use std::path::Path;
+
+/// An error which stops cooking.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum CookError {
+    /// The recipe can't be read, parsed or is invalid.
+    Config(String),
+    /// The crate metadata can't be obtained.
+    Metadata(String),
+    /// A hook can't be executed or has failed.
+    Hook(String),
+    /// The crate can't be built.
+    Build(String),
+    /// An ingredient or an artifact can't be collected.
+    Ingredient(String),
+    /// A container can't be created.
+    Container(String),
+    /// A hash can't be calculated or written.
+    Hash(String),
+    /// The cooked files can't be deployed.
+    #[cfg_attr(not(feature = "deploy"), allow(dead_code))]
+    Deploy(String),
+    /// The cooked archives don't match their hash files.
+    Verification(String),
+}
+
+pub type Result<T> = std::result::Result<T, CookError>;
+
+impl CookError {
+    /// Returns the exit code of the process for the error.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CookError::Config(_) => 2,
+            CookError::Metadata(_) => 3,
+            CookError::Hook(_) => 4,
+            CookError::Build(_) => 5,
+            CookError::Ingredient(_) => 6,
+            CookError::Container(_) => 7,
+            CookError::Hash(_) => 8,
+            CookError::Deploy(_) => 9,
+            CookError::Verification(_) => 10,
+        }
+    }
+}
+
+impl fmt::Display for CookError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CookError::Config(s)
+            | CookError::Metadata(s)
+            | CookError::Hook(s)
+            | CookError::Build(s)
+            | CookError::Ingredient(s)
+            | CookError::Container(s)
+            | CookError::Hash(s)
+            | CookError::Deploy(s)
+            | CookError::Verification(s) => f.write_str(s),
+        }
+    }
+}
+
+impl std::error::Error for CookError {}
+
+/// Returns the path as a string, the paths which are not valid UTF-8 can't
+/// be cooked.
+pub fn path_str(path: &Path) -> Result<&str> {
+    path.to_str().ok_or_else(|| {
+        CookError::Ingredient(format!("{} is not a valid UTF-8 path.", path.display()))
+    })
+}
@@ -1,59 +1,155 @@
 use std::collections::HashMap;
 use std::fs::File;
-use std::io::{Read, Write};
+use std::io::{self, Write};
 
 use crypto::digest::Digest;
 
+use crate::error::{CookError, Result};
+
+/// Returns a new hasher of the hash type.
+type HasherFn = fn() -> Box<dyn Digest>;
+
 lazy_static::lazy_static! {
-    static ref HASHES: HashMap<&'static str, fn(&[u8]) -> String> = {
+    static ref HASHES: HashMap<&'static str, HasherFn> = {
         let mut m = HashMap::new();
-        m.insert("md5", md5 as fn(&[u8]) -> String);
-        m.insert("sha256", sha256 as fn(&[u8]) -> String);
-        m.insert("sha512", sha512 as fn(&[u8]) -> String);
+        m.insert("md5", md5 as HasherFn);
+        m.insert("sha256", sha256 as HasherFn);
+        m.insert("sha512", sha512 as HasherFn);
         m
     };
 }
 
-fn md5(bytes: &[u8]) -> String {
-    use crypto::md5::Md5;
+fn md5() -> Box<dyn Digest> {
+    Box::new(crypto::md5::Md5::new())
+}
+
+fn sha256() -> Box<dyn Digest> {
+    Box::new(crypto::sha2::Sha256::new())
+}
 
-    let mut hasher = Md5::new();
-    hasher.input(bytes);
-    hasher.result_str()
+fn sha512() -> Box<dyn Digest> {
+    Box::new(crypto::sha2::Sha512::new())
 }
 
-fn sha256(bytes: &[u8]) -> String {
-    use crypto::sha2::Sha256;
+pub fn support_hash_type(hash: &str) -> bool {
+    HASHES.get::<str>(&hash.to_lowercase()).is_some()
+}
 
-    let mut hasher = Sha256::new();
-    hasher.input(bytes);
-    hasher.result_str()
+/// Passes the bytes through to the writer and hashes them with every hash
+/// type on the way, so that nothing is read twice.
+pub struct HashingWriter<W> {
+    inner: W,
+    hashers: Vec<(String, Box<dyn Digest>)>,
 }
 
-fn sha512(bytes: &[u8]) -> String {
-    use crypto::sha2::Sha512;
+impl<W: Write> HashingWriter<W> {
+    pub fn new(inner: W, hash_types: &[String]) -> Result<HashingWriter<W>> {
+        let mut hashers = Vec::new();
+        for hash_type in hash_types {
+            match HASHES.get::<str>(&hash_type.to_lowercase()) {
+                Some(f) => hashers.push((hash_type.clone(), f())),
+                None => {
+                    return Err(CookError::Hash(format!(
+                        "The \"{}\" hash type is unsupported.",
+                        hash_type
+                    )))
+                }
+            }
+        }
+        Ok(HashingWriter { inner, hashers })
+    }
 
-    let mut hasher = Sha512::new();
-    hasher.input(bytes);
-    hasher.result_str()
+    /// Returns the writer and the hashes of everything written, in the order
+    /// of the hash types.
+    pub fn finish(self) -> (W, Vec<(String, String)>) {
+        let hashes = self
+            .hashers
+            .into_iter()
+            .map(|(hash_type, mut h)| (hash_type, h.result_str()))
+            .collect();
+        (self.inner, hashes)
+    }
 }
 
-pub fn support_hash_type(hash: &str) -> bool {
-    HASHES.get::<str>(&hash.to_lowercase()).is_some()
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        for (_, h) in &mut self.hashers {
+            h.input(&buf[..written]);
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
 }
 
-pub fn hash(bytes: &[u8], hash: &str) -> String {
-    HASHES.get::<str>(&hash.to_lowercase()).unwrap()(bytes)
+/// Returns the hashes of the file read in one pass, in the order of the hash
+/// types.
+pub fn file_hashes(path: &str, hash_types: &[String]) -> Result<Vec<(String, String)>> {
+    let mut writer = HashingWriter::new(io::sink(), hash_types)?;
+    File::open(path)
+        .and_then(|mut f| io::copy(&mut f, &mut writer))
+        .map_err(|e| CookError::Hash(format!("Unable to read {}: {}", path, e)))?;
+    Ok(writer.finish().1)
 }
 
-pub fn file_hash(path: &str, hash_type: &str) -> String {
-    let mut bytes = Vec::new();
-    let mut f = File::open(path).unwrap();
-    let _ = f.read_to_end(&mut bytes);
-    hash(&bytes, hash_type)
+/// Writes the hash into the hash file next to the archive.
+pub fn write_hash_file(destination: &str, hash: &str) -> Result<()> {
+    File::create(destination)
+        .and_then(|mut f| writeln!(f, "{}", hash))
+        .map_err(|e| CookError::Hash(format!("Unable to write {}: {}", destination, e)))
 }
 
-pub fn write_file_hash(source: &str, destination: &str, hash_type: &str) {
-    let mut f = File::create(destination).unwrap();
-    let _ = writeln!(f, "{}", file_hash(source, hash_type));
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Takes one byte at a time, as the writers may.
+    struct ByteWriter(Vec<u8>);
+
+    impl Write for ByteWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.extend(buf.iter().take(1));
+            Ok(buf.len().min(1))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn hashes() {
+        let hash_types = ["md5".to_owned(), "SHA256".to_owned()];
+        let mut writer = HashingWriter::new(ByteWriter(Vec::new()), &hash_types).unwrap();
+        writer.write_all(b"a").unwrap();
+        writer.write_all(b"bc").unwrap();
+        let (inner, hashes) = writer.finish();
+        assert_eq!(inner.0, b"abc");
+        assert_eq!(
+            hashes,
+            [
+                (
+                    "md5".to_owned(),
+                    "900150983cd24fb0d6963f7d28e17f72".to_owned()
+                ),
+                (
+                    "SHA256".to_owned(),
+                    "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad".to_owned()
+                ),
+            ]
+        );
+
+        let (_, hashes) = HashingWriter::new(io::sink(), &["sha512".to_owned()])
+            .unwrap()
+            .finish();
+        assert_eq!(
+            hashes[0].1,
+            "cf83e1357eefb8bdf1542850d66d8007d620e4050b5715dc83f4a921d36ce9ce\
+             47d0d13c5d85f2b0ff8318d2877eec2f63b931bd47417a81a538327af927da3e"
+        );
+        assert!(HashingWriter::new(io::sink(), &["crc32".to_owned()]).is_err());
+    }
 }
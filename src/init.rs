@@ -0,0 +1,165 @@
+use crate::error::{path_str, CookError, Result};
+use crate::metadata::Package;
+use crate::term_print::*;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+const CONTAINERS: &[&str] = &["tar", "tar.bzip2"];
+const HASHES: &[&str] = &["sha256"];
+const COOK_DIRECTORY: &str = "cooked";
+const FILE_PREFIXES: &[&str] = &["license", "licence", "copying", "readme", "changelog"];
+const ASSET_DIRECTORIES: &[&str] = &[
+    "assets",
+    "resources",
+    "res",
+    "data",
+    "config",
+    "static",
+    "share",
+    "templates",
+    "shaders",
+];
+/// The library crate types which produce something worth cooking.
+const LIBRARY_KINDS: &[&str] = &["cdylib", "staticlib"];
+
+fn quote(s: &str) -> String {
+    toml::Value::String(s.to_owned()).to_string()
+}
+
+fn list(items: &[&str]) -> String {
+    let items: Vec<String> = items.iter().map(|i| quote(i)).collect();
+    format!("[{}]", items.join(", "))
+}
+
+/// Returns the artifacts of the package as `(name, kind)`.
+fn artifacts(package: &Package) -> Vec<(String, String)> {
+    let mut artifacts = Vec::new();
+    for t in &package.targets {
+        if t.kind.iter().any(|k| k == "bin") {
+            artifacts.push((t.name.clone(), "bin".to_owned()));
+        }
+        for k in t
+            .crate_types
+            .iter()
+            .filter(|k| LIBRARY_KINDS.contains(&k.as_str()))
+        {
+            artifacts.push((t.name.clone(), k.clone()));
+        }
+    }
+    artifacts
+}
+
+/// Returns the license files, the readme and the asset directories of the
+/// package as paths relative to the current directory.
+fn ingredients(root: &Path) -> Result<(Vec<String>, Vec<String>)> {
+    let cwd = std::env::current_dir().map_err(|e| {
+        CookError::Ingredient(format!("Unable to read the current directory: {}", e))
+    })?;
+    let relative = |p: &Path| path_str(p.strip_prefix(&cwd).unwrap_or(p)).map(|p| p.to_owned());
+
+    let mut files = Vec::new();
+    let mut directories = Vec::new();
+    let mut entries: Vec<_> = fs::read_dir(root)
+        .map_err(|e| CookError::Config(format!("Unable to read {}: {}", root.display(), e)))?
+        .filter_map(|e| e.ok())
+        .collect();
+    entries.sort_by_key(|e| e.file_name());
+    for e in entries {
+        let name = e.file_name().to_string_lossy().to_lowercase();
+        let path = e.path();
+        if path.is_file() && FILE_PREFIXES.iter().any(|p| name.starts_with(p)) {
+            files.push(relative(&path)?);
+        } else if path.is_dir() && ASSET_DIRECTORIES.contains(&name.as_str()) {
+            directories.push(relative(&path)?);
+        }
+    }
+    Ok((files, directories))
+}
+
+fn ingredient(source: &str) -> String {
+    let destination = Path::new(source).file_name().unwrap().to_str().unwrap();
+    format!(
+        "[[cook.ingredient]]\nsource = {}\ndestination = {}\n",
+        quote(source),
+        quote(destination)
+    )
+}
+
+/// Returns a commented recipe for cooking the package.
+fn recipe(package: &Package) -> Result<String> {
+    let containers: Vec<&str> = CONTAINERS
+        .iter()
+        .copied()
+        .filter(|c| crate::container::support_container(c))
+        .collect();
+    let root = Path::new(&package.manifest_path).parent().unwrap();
+    let (files, directories) = ingredients(root)?;
+    // The recipe sections, separated by empty lines.
+    let mut sections = Vec::new();
+
+    let mut cook = String::new();
+    let _ = writeln!(
+        cook,
+        "# The recipe for cooking {} with `cargo cook`.",
+        package.name
+    );
+    let _ = writeln!(cook, "[cook]");
+    let _ = writeln!(
+        cook,
+        "# Containers to pack the artifacts and the ingredients into."
+    );
+    let _ = writeln!(cook, "containers = {}", list(&containers));
+    let _ = writeln!(cook, "# Hash sums to calculate for every container.");
+    let _ = writeln!(cook, "hashes = {}", list(HASHES));
+    let _ = writeln!(cook, "# A directory to put the containers into.");
+    let _ = writeln!(cook, "cook_directory = {}", quote(COOK_DIRECTORY));
+    sections.push(cook);
+
+    sections.push(
+        "# Build the crate before cooking.\n[cook.build]\nprofile = \"release\"\n".to_owned(),
+    );
+
+    for (i, (name, kind)) in artifacts(package).iter().enumerate() {
+        let mut artifact = String::new();
+        if i == 0 {
+            let _ = writeln!(
+                artifact,
+                "# The crate artifacts to put into the containers."
+            );
+        }
+        let _ = writeln!(artifact, "[[cook.artifact]]");
+        let _ = writeln!(artifact, "name = {}", quote(name));
+        let _ = writeln!(artifact, "kind = {}", quote(kind));
+        sections.push(artifact);
+    }
+
+    for (i, source) in files.iter().chain(directories.iter()).enumerate() {
+        let mut ingredient = String::new();
+        if i == 0 {
+            let _ = writeln!(
+                ingredient,
+                "# Files and directories to put into the containers along with the artifacts.\n\
+                 # A directory ingredient may have a `filter` regular expression."
+            );
+        }
+        ingredient.push_str(&self::ingredient(source));
+        sections.push(ingredient);
+    }
+    Ok(sections.join("\n"))
+}
+
+/// Writes a recipe for cooking the package, an existing recipe is only
+/// overwritten when forced.
+pub fn init(package: &Package, recipe_file_name: &str, force: bool) -> Result<()> {
+    if Path::new(recipe_file_name).exists() && !force {
+        return Err(CookError::Config(format!(
+            "{} already exists, use --force to overwrite it.",
+            recipe_file_name
+        )));
+    }
+    fs::write(recipe_file_name, recipe(package)?)
+        .map_err(|e| CookError::Config(format!("Unable to write {}: {}", recipe_file_name, e)))?;
+    term_println(term::color::BRIGHT_GREEN, "Created", recipe_file_name);
+    Ok(())
+}
@@ -0,0 +1,290 @@
+use crate::config::Cook;
+use crate::error::{CookError, Result};
+use regex::Regex;
+use std::cell::OnceCell;
+use std::env;
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The fields which are used as is: the regular expressions.
+const VERBATIM_FIELDS: &[&str] = &["filter"];
+const SECONDS_IN_DAY: u64 = 86_400;
+/// The operating systems as they appear in the target triples.
+const OPERATING_SYSTEMS: &[&str] = &[
+    "linux",
+    "windows",
+    "darwin",
+    "ios",
+    "android",
+    "freebsd",
+    "netbsd",
+    "openbsd",
+    "dragonfly",
+    "solaris",
+    "illumos",
+    "fuchsia",
+    "redox",
+    "haiku",
+    "wasi",
+    "emscripten",
+    "none",
+];
+
+lazy_static::lazy_static! {
+    /// `{name}` or `{name:argument}`, the name can't start with a digit so
+    /// that `{2}` stays what it is.
+    static ref PLACEHOLDER: Regex =
+        Regex::new(r"\{([A-Za-z_][A-Za-z0-9_]*)(?::([^{}]*))?\}").unwrap();
+}
+
+/// The values of the placeholders for cooking a package for a target.
+pub struct Variables {
+    name: String,
+    version: String,
+    target: String,
+    profile: String,
+    /// The directory to take the git commit of.
+    root: PathBuf,
+    git_sha: OnceCell<String>,
+}
+
+impl Variables {
+    pub fn new(name: &str, version: &str, target: &str, profile: &str, root: PathBuf) -> Variables {
+        Variables {
+            name: name.to_owned(),
+            version: version.to_owned(),
+            target: target.to_owned(),
+            profile: profile.to_owned(),
+            root,
+            git_sha: OnceCell::new(),
+        }
+    }
+
+    fn git_sha(&self) -> Result<String> {
+        if let Some(sha) = self.git_sha.get() {
+            return Ok(sha.clone());
+        }
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(&self.root)
+            .args(["rev-parse", "--short", "HEAD"])
+            .output()
+            .map_err(|e| CookError::Metadata(format!("Unable to run git: {}", e)))?;
+        if !output.status.success() {
+            return Err(CookError::Metadata(format!(
+                "Unable to get the git commit of {}: {}",
+                self.root.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        let sha = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+        Ok(self.git_sha.get_or_init(|| sha).clone())
+    }
+
+    /// Returns the value of the placeholder in the recipe field, `None` if
+    /// there is no such placeholder.
+    fn value(&self, name: &str, argument: Option<&str>, path: &str) -> Option<Result<String>> {
+        Some(match (name, argument) {
+            ("name", None) => Ok(self.name.clone()),
+            ("version", None) => Ok(self.version.clone()),
+            ("target", None) => Ok(self.target.clone()),
+            ("arch", None) => Ok(arch(&self.target).to_owned()),
+            ("os", None) => Ok(os(&self.target).to_owned()),
+            ("profile", None) => Ok(self.profile.clone()),
+            ("git_sha", None) => self.git_sha(),
+            ("date", None) => Ok(date()),
+            ("env", Some(var)) => env::var(var).map_err(|_| {
+                CookError::Config(format!(
+                    "The environment variable {} used in {} is not set.",
+                    var, path
+                ))
+            }),
+            _ => return None,
+        })
+    }
+}
+
+/// Returns the architecture of the target triple, `x86_64` of `x86_64-unknown-linux-gnu`.
+fn arch(triple: &str) -> &str {
+    triple.split('-').next().unwrap_or(triple)
+}
+
+/// Returns the operating system of the target triple, `linux` of
+/// `x86_64-unknown-linux-gnu` or `unknown` if there is none.
+fn os(triple: &str) -> &str {
+    triple
+        .split('-')
+        .skip(1)
+        .find(|c| OPERATING_SYSTEMS.contains(c))
+        .unwrap_or("unknown")
+}
+
+/// Returns the seconds since the epoch of `SOURCE_DATE_EPOCH`, the time the
+/// reproducible builds use instead of the current one.
+pub fn source_date_epoch() -> Option<u64> {
+    env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+}
+
+/// Returns the current UTC date as `YYYY-MM-DD`, or the one of
+/// `SOURCE_DATE_EPOCH` if it is set.
+fn date() -> String {
+    let seconds = source_date_epoch().unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0)
+    });
+    // The days since the epoch to the civil date, as in
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = seconds / SECONDS_IN_DAY + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Replaces the placeholders in the string, the path is the recipe field
+/// the string is taken from.
+fn interpolate_str(s: &str, path: &str, v: &Variables) -> Result<String> {
+    let mut result = String::new();
+    let mut last = 0;
+    for c in PLACEHOLDER.captures_iter(s) {
+        let placeholder = c.get(0).unwrap();
+        let value = v
+            .value(&c[1], c.get(2).map(|a| a.as_str()), path)
+            .unwrap_or_else(|| {
+                Err(CookError::Config(format!(
+                    "Unknown placeholder {} in {}.",
+                    placeholder.as_str(),
+                    path
+                )))
+            })?;
+        result.push_str(&s[last..placeholder.start()]);
+        result.push_str(&value);
+        last = placeholder.end();
+    }
+    result.push_str(&s[last..]);
+    Ok(result)
+}
+
+fn interpolate_value(value: &mut toml::Value, path: &str, v: &Variables) -> Result<()> {
+    match value {
+        toml::Value::String(s) => *s = interpolate_str(s, path, v)?,
+        toml::Value::Array(a) => {
+            for (i, item) in a.iter_mut().enumerate() {
+                interpolate_value(item, &format!("{}[{}]", path, i), v)?;
+            }
+        }
+        toml::Value::Table(t) => {
+            for (k, item) in t.iter_mut() {
+                if !VERBATIM_FIELDS.contains(&k.as_str()) {
+                    interpolate_value(item, &format!("{}.{}", path, k), v)?;
+                }
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Returns the recipe with the placeholders in all the string values replaced.
+pub fn interpolate(c: &Cook, v: &Variables) -> Result<Cook> {
+    let mut value = toml::Value::try_from(c)
+        .map_err(|e| CookError::Config(format!("Unable to serialize the recipe: {}", e)))?;
+    interpolate_value(&mut value, "cook", v)?;
+    value
+        .try_into()
+        .map_err(|e| CookError::Config(format!("Unable to interpolate the recipe: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variables() -> Variables {
+        Variables::new(
+            "cook",
+            "0.1.0",
+            "x86_64-unknown-linux-gnu",
+            "release",
+            PathBuf::from("."),
+        )
+    }
+
+    #[test]
+    fn date_of_source_date_epoch() {
+        let dates = [
+            (0, "1970-01-01"),
+            (86_399, "1970-01-01"),
+            (946_598_400, "1999-12-31"),
+            (951_782_400, "2000-02-29"),
+            (951_868_800, "2000-03-01"),
+            (1_709_164_800, "2024-02-29"),
+        ];
+        for (epoch, expected) in dates.iter() {
+            env::set_var("SOURCE_DATE_EPOCH", epoch.to_string());
+            assert_eq!(date(), *expected, "SOURCE_DATE_EPOCH={}", epoch);
+        }
+        env::remove_var("SOURCE_DATE_EPOCH");
+    }
+
+    #[test]
+    fn placeholders() {
+        let v = variables();
+        assert_eq!(
+            interpolate_str("{name}-{version}-{arch}-{os}", "cook.archive_name", &v).unwrap(),
+            "cook-0.1.0-x86_64-linux"
+        );
+        assert_eq!(
+            interpolate_str("a{2}b{}", "cook.archive_name", &v).unwrap(),
+            "a{2}b{}"
+        );
+    }
+
+    #[test]
+    fn unknown_placeholder() {
+        let e = interpolate_str("{nmae}", "cook.archive_name", &variables()).unwrap_err();
+        assert_eq!(
+            e,
+            CookError::Config("Unknown placeholder {nmae} in cook.archive_name.".to_owned())
+        );
+        assert!(interpolate_str("{name:x}", "cook.archive_name", &variables()).is_err());
+    }
+
+    #[test]
+    fn unset_environment_variable() {
+        let e = interpolate_str(
+            "{env:COOK_TEST_UNSET_VARIABLE}",
+            "cook.cook_directory",
+            &variables(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            e,
+            CookError::Config(
+                "The environment variable COOK_TEST_UNSET_VARIABLE used in cook.cook_directory \
+                 is not set."
+                    .to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn triples() {
+        assert_eq!(arch("aarch64-apple-darwin"), "aarch64");
+        assert_eq!(os("aarch64-apple-darwin"), "darwin");
+        assert_eq!(os("x86_64-pc-windows-msvc"), "windows");
+        assert_eq!(os("thumbv7em-none-eabihf"), "none");
+        assert_eq!(os("wasm32-unknown-unknown"), "unknown");
+        assert_eq!(arch("riscv64gc"), "riscv64gc");
+        assert_eq!(os("riscv64gc"), "unknown");
+    }
+}
@@ -1,33 +1,108 @@
+mod artifact;
+mod build;
 mod config;
 mod container;
+#[cfg(feature = "dependencies")]
+mod dependencies;
 #[cfg(feature = "deploy")]
 mod deploy;
+mod error;
 mod hash;
+mod init;
+mod interpolate;
+mod message;
+mod metadata;
 mod term_print;
+mod validate;
+mod vendor;
+mod verify;
+mod workspace;
 
-use clap::{App, AppSettings, Arg, SubCommand};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 use regex::Regex;
+use serde_json::json;
 
+use std::collections::HashSet;
 use std::fs;
 #[cfg(not(debug_assertions))]
 use std::panic;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
 use config::*;
+use error::{path_str, CookError, Result};
+use metadata::{Metadata, Package};
 use term_print::*;
 
 const CONFIG_FILE_NAME: &str = "Cook.toml";
-const CARGO_TOML: &str = "Cargo.toml";
 const COMMAND_NAME: &str = "cook";
 const COMMAND_DESCRIPTION: &str = "A third-party cargo extension which cooks your crate.";
 const COMMAND_AUTHOR: &str = "Victor Polevoy <maintainer@thefx.co>";
 const COMMAND_RECIPE_ARG_NAME: &str = "recipe";
+const COMMAND_TARGET_ARG_NAME: &str = "target";
+const COMMAND_WORKSPACE_ARG_NAME: &str = "workspace";
+const COMMAND_PACKAGE_ARG_NAME: &str = "package";
+const COMMAND_VENDOR_ARG_NAME: &str = "vendor";
+const COMMAND_DRY_RUN_ARG_NAME: &str = "dry-run";
+const COMMAND_MESSAGE_FORMAT_ARG_NAME: &str = "message-format";
+const COMMAND_PROFILE_ARG_NAME: &str = "profile";
+const COMMAND_SET_ARG_NAME: &str = "set";
+const COMMAND_VERIFY_REPRODUCIBLE_ARG_NAME: &str = "verify-reproducible";
+const INIT_COMMAND_NAME: &str = "init";
+const INIT_COMMAND_DESCRIPTION: &str = "Creates a recipe for cooking the crate.";
+const INIT_FORCE_ARG_NAME: &str = "force";
+const VERIFY_COMMAND_NAME: &str = "verify";
+const VERIFY_COMMAND_DESCRIPTION: &str = "Checks the cooked archives against their hash files.";
+const VERIFY_PATH_ARG_NAME: &str = "PATH";
+const CHECK_COMMAND_NAME: &str = "check";
+const CHECK_COMMAND_DESCRIPTION: &str =
+    "Checks the recipe and all of its profiles without cooking anything.";
+/// The suffix of the archives with the vendored sources.
+const VENDOR_SUFFIX: &str = "src";
+
+/// Options of the cooking passed through the command line.
+struct CookOptions<'a> {
+    /// The recipe file, `None` if the recipe is in `Cargo.toml`.
+    recipe: Option<&'a str>,
+    profiles: Vec<&'a str>,
+    /// The fields set by the environment and the command line.
+    overrides: Vec<Override>,
+    targets: Vec<&'a str>,
+    workspace: bool,
+    packages: Vec<&'a str>,
+    vendor: bool,
+    dry_run: bool,
+    /// Pack the files twice and compare the containers before cooking.
+    verify_reproducible: bool,
+}
+
+/// A package which is built but not packed yet.
+struct Prepared {
+    /// The recipe for the host, the one of the hooks and the vendored sources.
+    config: CookConfig,
+    /// The target triples, `None` for the host, with their recipes.
+    units: Vec<(Option<String>, CookConfig)>,
+}
+
+/// A package which is cooked but not deployed yet.
+struct Cooked {
+    /// The recipe for the host, the one of the post-cook hook.
+    config: CookConfig,
+    /// The cooked files with the recipe they have been cooked by.
+    #[cfg_attr(not(feature = "deploy"), allow(dead_code))]
+    files: Vec<(CookConfig, Vec<String>)>,
+}
 
 fn main() {
     #[cfg(not(debug_assertions))]
     panic::set_hook(Box::new(|panic_info| {
-        term_panic(panic_info.payload().downcast_ref::<String>().unwrap());
+        let payload = panic_info.payload();
+        let message = payload
+            .downcast_ref::<String>()
+            .map(String::as_str)
+            .or_else(|| payload.downcast_ref::<&str>().copied())
+            .unwrap_or("Unexpected failure.");
+        term_error(message);
     }));
 
     let matches = App::new(format!("cargo-{}", COMMAND_NAME))
@@ -50,221 +125,1101 @@ fn main() {
                         .default_value(CONFIG_FILE_NAME)
                         .help("Sets the recipe file to use for cooking.")
                         .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name(COMMAND_TARGET_ARG_NAME)
+                        .long(COMMAND_TARGET_ARG_NAME)
+                        .value_name("TRIPLE")
+                        .help(
+                            "Cooks the crate for the target triple, overrides the recipe targets.",
+                        )
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1),
+                )
+                .arg(
+                    Arg::with_name(COMMAND_WORKSPACE_ARG_NAME)
+                        .long(COMMAND_WORKSPACE_ARG_NAME)
+                        .help("Cooks every member of the workspace."),
+                )
+                .arg(
+                    Arg::with_name(COMMAND_PACKAGE_ARG_NAME)
+                        .short("p")
+                        .long(COMMAND_PACKAGE_ARG_NAME)
+                        .value_name("SPEC")
+                        .help("Cooks only the specified workspace member.")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1),
+                )
+                .arg(
+                    Arg::with_name(COMMAND_VENDOR_ARG_NAME)
+                        .long(COMMAND_VENDOR_ARG_NAME)
+                        .help("Cooks the sources with the vendored dependencies instead."),
+                )
+                .arg(
+                    Arg::with_name(COMMAND_DRY_RUN_ARG_NAME)
+                        .long(COMMAND_DRY_RUN_ARG_NAME)
+                        .help("Prints what cooking would do without doing anything."),
+                )
+                .arg(
+                    Arg::with_name(COMMAND_VERIFY_REPRODUCIBLE_ARG_NAME)
+                        .long(COMMAND_VERIFY_REPRODUCIBLE_ARG_NAME)
+                        .help("Fails if packing the files twice gives different containers."),
+                )
+                .arg(
+                    Arg::with_name(COMMAND_PROFILE_ARG_NAME)
+                        .long(COMMAND_PROFILE_ARG_NAME)
+                        .value_name("NAME")
+                        .help("Applies the overrides of the recipe profile, can be repeated.")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1),
+                )
+                .arg(
+                    Arg::with_name(COMMAND_SET_ARG_NAME)
+                        .long(COMMAND_SET_ARG_NAME)
+                        .value_name("FIELD=VALUE")
+                        .help("Sets a recipe field, as `cook.cook_directory=out`, can be repeated.")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1),
+                )
+                .arg(
+                    Arg::with_name(COMMAND_MESSAGE_FORMAT_ARG_NAME)
+                        .long(COMMAND_MESSAGE_FORMAT_ARG_NAME)
+                        .value_name("FMT")
+                        .possible_values(message::FORMATS)
+                        .default_value(message::FORMATS[0])
+                        .help("Prints the progress as human readable text or as JSON lines."),
+                )
+                .subcommand(
+                    SubCommand::with_name(INIT_COMMAND_NAME)
+                        .about(INIT_COMMAND_DESCRIPTION)
+                        .arg(
+                            Arg::with_name(INIT_FORCE_ARG_NAME)
+                                .long(INIT_FORCE_ARG_NAME)
+                                .help("Overwrites the existing recipe."),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name(VERIFY_COMMAND_NAME)
+                        .about(VERIFY_COMMAND_DESCRIPTION)
+                        .arg(Arg::with_name(VERIFY_PATH_ARG_NAME).help(
+                            "An archive or a directory to verify, the cook directory by default.",
+                        )),
+                )
+                .subcommand(
+                    SubCommand::with_name(CHECK_COMMAND_NAME).about(CHECK_COMMAND_DESCRIPTION),
                 ),
         )
         .settings(&[AppSettings::SubcommandRequired])
         .get_matches();
 
-    cook(
+    match run(&matches) {
+        Ok(()) => message::emit("finished", json!({ "success": true })),
+        Err(e) => {
+            message::emit(
+                "finished",
+                json!({
+                    "success": false,
+                    "exit_code": e.exit_code(),
+                    "message": e.to_string(),
+                }),
+            );
+            term_error(&e.to_string());
+            std::process::exit(e.exit_code());
+        }
+    }
+}
+
+fn run(matches: &ArgMatches) -> Result<()> {
+    let matches = matches
+        .subcommand_matches(COMMAND_NAME)
+        .expect("The binary hasn't been invoked as a subcommand.");
+    message::set_format(
         matches
-            .subcommand_matches(COMMAND_NAME)
-            .expect("The binary hasn't been invoked as a subcommand.")
-            .value_of(COMMAND_RECIPE_ARG_NAME)
-            .unwrap_or(CONFIG_FILE_NAME),
+            .value_of(COMMAND_MESSAGE_FORMAT_ARG_NAME)
+            .unwrap_or(message::FORMATS[0]),
     );
+    let recipe_file_name = matches
+        .value_of(COMMAND_RECIPE_ARG_NAME)
+        .unwrap_or(CONFIG_FILE_NAME);
+
+    if let Some(init_matches) = matches.subcommand_matches(INIT_COMMAND_NAME) {
+        let metadata = metadata::load()?;
+        let package = workspace::packages(&metadata, false, &[])?.remove(0);
+        return init::init(
+            &package,
+            recipe_file_name,
+            init_matches.is_present(INIT_FORCE_ARG_NAME),
+        );
+    }
+
+    // An explicit recipe file wins over the default one, which wins over the
+    // recipe in `Cargo.toml`.
+    let recipe = if matches.occurrences_of(COMMAND_RECIPE_ARG_NAME) > 0
+        || Path::new(CONFIG_FILE_NAME).exists()
+    {
+        Some(recipe_file_name)
+    } else {
+        None
+    };
+
+    let profiles: Vec<&str> = matches
+        .values_of(COMMAND_PROFILE_ARG_NAME)
+        .map(|v| v.collect())
+        .unwrap_or_default();
+
+    // The command line wins over the environment.
+    let mut overrides = Override::from_env()?;
+    for s in matches
+        .values_of(COMMAND_SET_ARG_NAME)
+        .into_iter()
+        .flatten()
+    {
+        let (field, value) = s
+            .split_once('=')
+            .ok_or_else(|| CookError::Config(format!("The --set {} is not FIELD=VALUE.", s)))?;
+        overrides.push(Override::new(field.trim(), value, format!("--set {}", s))?);
+    }
+
+    if let Some(verify_matches) = matches.subcommand_matches(VERIFY_COMMAND_NAME) {
+        let metadata = metadata::load()?;
+        let package = workspace::packages(&metadata, false, &[])
+            .ok()
+            .map(|mut p| p.remove(0));
+        let mut cook_config = match recipe {
+            Some(file_name) => config::load(file_name)?,
+            None => CookConfig {
+                cook: metadata_recipe(&metadata, package.as_ref())?,
+            },
+        };
+        cook_config.cook = cook_config.cook.profiles(&profiles)?;
+        if let Some(ref p) = package {
+            cook_config.cook = cook_config.cook.member(&p.name)?;
+        }
+        cook_config.cook = cook_config.cook.overridden(&overrides)?;
+        if let Some(ref p) = package {
+            let host = build::host_triple()?;
+            cook_config = unit_config(&cook_config, p, &host, recipe, &overrides)?;
+        }
+        validate::report(
+            validate::overridden(validate::recipe(&cook_config.cook), &overrides),
+            recipe,
+        )?;
+        let path = verify_matches
+            .value_of(VERIFY_PATH_ARG_NAME)
+            .unwrap_or(&cook_config.cook.cook_directory);
+        return verify::verify(&cook_config.cook, path);
+    }
+
+    let options = CookOptions {
+        recipe,
+        profiles,
+        overrides,
+        targets: matches
+            .values_of(COMMAND_TARGET_ARG_NAME)
+            .map(|v| v.collect())
+            .unwrap_or_default(),
+        workspace: matches.is_present(COMMAND_WORKSPACE_ARG_NAME),
+        packages: matches
+            .values_of(COMMAND_PACKAGE_ARG_NAME)
+            .map(|v| v.collect())
+            .unwrap_or_default(),
+        vendor: matches.is_present(COMMAND_VENDOR_ARG_NAME),
+        dry_run: matches.is_present(COMMAND_DRY_RUN_ARG_NAME),
+        verify_reproducible: matches.is_present(COMMAND_VERIFY_REPRODUCIBLE_ARG_NAME),
+    };
+    if matches.subcommand_matches(CHECK_COMMAND_NAME).is_some() {
+        return check(&options);
+    }
+    cook(&options)
 }
 
-fn cook(cook_config_name: &str) {
-    let cook_config = load_config::<CookConfig>(cook_config_name);
-    let cargo_config = load_config::<CargoConfig>(CARGO_TOML);
+/// Returns the target triples to cook for, `None` stands for the host.
+fn target_triples(c: &CookConfig, o: &CookOptions) -> Result<Vec<Option<String>>> {
+    let triples: Vec<String> = if !o.targets.is_empty() {
+        o.targets.iter().map(|t| (*t).to_owned()).collect()
+    } else {
+        c.cook.targets.clone().unwrap_or_default()
+    };
+    if triples.is_empty() {
+        return Ok(vec![None]);
+    }
+    if c.cook.target_directory.is_some() {
+        return Err(CookError::Config(
+            "The target_directory can not be used together with target triples.".to_owned(),
+        ));
+    }
+    Ok(triples.into_iter().map(Some).collect())
+}
+
+/// Returns the recipe from the metadata of `Cargo.toml`, for when there is no
+/// recipe file.
+fn metadata_recipe(m: &Metadata, package: Option<&Package>) -> Result<Cook> {
+    m.recipe(package)?.ok_or_else(|| {
+        CookError::Config(format!(
+            "There is neither {} nor [package.metadata.cook] in Cargo.toml.",
+            CONFIG_FILE_NAME
+        ))
+    })
+}
+
+fn cook(o: &CookOptions) -> Result<()> {
+    let cook_config_name = o.recipe;
+    let cook_config = match cook_config_name {
+        Some(file_name) => Some(config::load(file_name)?),
+        None => None,
+    };
+    let metadata = metadata::load()?;
     #[cfg(debug_assertions)]
-    println!(
-        "Config file name: {}\nConfig contents: {:?}",
+    eprintln!(
+        "Config file name: {:?}\nConfig contents: {:?}",
         cook_config_name, cook_config
     );
-    parse_config(&cook_config);
-    let pkg_name = &format!(
-        "{} v{}",
-        cargo_config.package.name, cargo_config.package.version
+
+    let packages = workspace::packages(&metadata, o.workspace, &o.packages)?;
+    // All the packages are built and verified before any of them is packed.
+    let mut prepared = Vec::new();
+    // The archives of all the packages and triples, the same names would
+    // overwrite each other.
+    let mut names = HashSet::new();
+    let mut result = packages.iter().try_for_each(|package| {
+        let recipe = match cook_config {
+            Some(ref c) => c.cook.clone(),
+            None => metadata_recipe(&metadata, Some(package))?,
+        };
+        let member_config = CookConfig {
+            cook: recipe
+                .profiles(&o.profiles)?
+                .member(&package.name)?
+                .overridden(&o.overrides)?,
+        };
+        prepared.push((
+            package,
+            prepare_package(&member_config, &metadata, package, o, &mut names)?,
+        ));
+        Ok(())
+    });
+    let mut cooked = Vec::new();
+    result = result.and_then(|_| {
+        prepared.iter().try_for_each(|(package, p)| {
+            cooked.push(cook_package(p, &metadata, package, o)?);
+            Ok(())
+        })
+    });
+    // Everything cooked is deployed at once, so that each target is
+    // connected to only once.
+    #[cfg(feature = "deploy")]
+    {
+        result = result.and_then(|_| deploy(&cooked, o.dry_run));
+    }
+    result = result.and_then(|_| {
+        cooked
+            .iter()
+            .try_for_each(|p| cook_hook(&p.config.cook, false, o.dry_run))
+    });
+    let _ = fs::remove_dir_all(staging_directory());
+    result?;
+    term_println(term::color::BRIGHT_GREEN, "Finished", "cooking");
+    Ok(())
+}
+
+/// Returns all the problems of the recipe of the package for the host and
+/// every target triple, including the missing ingredients and hooks.
+fn unit_problems(
+    recipe: &CookConfig,
+    package: &Package,
+    host: &str,
+    o: &CookOptions,
+) -> Vec<validate::Diagnostic> {
+    let c = match interpolated_config(recipe, package, host) {
+        Ok(c) => c,
+        Err(e) => return vec![e.into()],
+    };
+    let mut problems = validate::hooks(&c.cook);
+    let triples = match target_triples(&c, o) {
+        Ok(t) => t,
+        Err(e) => {
+            problems.push(e.into());
+            vec![None]
+        }
+    };
+    for triple in triples {
+        match interpolated_config(recipe, package, triple.as_deref().unwrap_or(host)) {
+            Ok(c) => {
+                problems.extend(validate::recipe(&c.cook));
+                problems.extend(validate::ingredients(&c.cook));
+            }
+            Err(e) => problems.push(e.into()),
+        }
+    }
+    problems
+}
+
+/// Checks the recipe of every package with no profiles and with every
+/// profile of it, or with the chosen ones, and reports all the problems.
+fn check(o: &CookOptions) -> Result<()> {
+    let mut problems: Vec<validate::Diagnostic> = Vec::new();
+    let cook_config = match o.recipe {
+        Some(file_name) => {
+            // The fields are checked with the values, the values which are
+            // read are checked even if some fields are wrong.
+            let (config, files) = config::load_checked(file_name)?;
+            for (file, diagnostics) in files {
+                for mut d in diagnostics {
+                    if file != file_name {
+                        d.origin = Some(file.clone());
+                    }
+                    problems.push(d);
+                }
+            }
+            match config {
+                Ok(c) => Some(c),
+                Err(e) => {
+                    // The recipe can't be read without the fields of a wrong
+                    // type when they are required, they are reported already.
+                    if problems.is_empty() {
+                        problems.push(e.into());
+                    }
+                    return validate::report(problems, o.recipe);
+                }
+            }
+        }
+        None => None,
+    };
+    let metadata = metadata::load()?;
+    let packages = workspace::packages(&metadata, o.workspace, &o.packages)?;
+    let host = build::host_triple()?;
+
+    let mut seen = HashSet::new();
+    for package in &packages {
+        let recipe = match cook_config {
+            Some(ref c) => c.cook.clone(),
+            None => metadata_recipe(&metadata, Some(package))?,
+        };
+        let profile_sets: Vec<Vec<&str>> = if o.profiles.is_empty() {
+            std::iter::once(Vec::new())
+                .chain(
+                    recipe
+                        .profile
+                        .iter()
+                        .flat_map(|p| p.keys())
+                        .map(|n| vec![n.as_str()]),
+                )
+                .collect()
+        } else {
+            vec![o.profiles.clone()]
+        };
+        for profiles in profile_sets {
+            let mut context = Vec::new();
+            if packages.len() > 1 {
+                context.push(format!("the {} package", package.name));
+            }
+            if !profiles.is_empty() {
+                context.push(format!(
+                    "the \"{}\" profile{}",
+                    profiles.join("\", \""),
+                    if profiles.len() > 1 { "s" } else { "" }
+                ));
+            }
+            let unit_problems = recipe
+                .profiles(&profiles)
+                .and_then(|r| r.member(&package.name))
+                .and_then(|r| r.overridden(&o.overrides))
+                .map(|cook| unit_problems(&CookConfig { cook }, package, &host, o));
+            let unit_problems = match unit_problems {
+                Ok(p) => validate::overridden(p, &o.overrides),
+                Err(e) => vec![e.into()],
+            };
+            // The problems of the recipe show up with every profile, the new
+            // ones come from the profile.
+            for mut d in unit_problems {
+                if seen.insert((d.path.clone(), d.message.clone())) {
+                    if let [profile] = profiles[..] {
+                        d.path = d.path.replacen(
+                            "cook",
+                            &format!("cook.profile.{}", validate::quote_key(profile)),
+                            1,
+                        );
+                    }
+                    if !context.is_empty() {
+                        d.message = format!("In {}: {}", context.join(" with "), d.message);
+                    }
+                    problems.push(d);
+                }
+            }
+        }
+    }
+
+    validate::report(problems, o.recipe)?;
+    term_println(
+        term::color::BRIGHT_GREEN,
+        "Finished",
+        "checking, no problems found",
     );
-    term_println(term::color::BRIGHT_GREEN, "Cooking", pkg_name);
-    cook_hook(&cook_config.cook, true);
+    Ok(())
+}
 
-    archive(
-        &cook_config,
-        &cargo_config,
-        collect(&cook_config, &cargo_config),
+/// Returns the recipe for cooking the package for the target triple with all
+/// the placeholders replaced, the recipe file is where the problems point.
+fn unit_config(
+    c: &CookConfig,
+    package: &Package,
+    triple: &str,
+    recipe: Option<&str>,
+    overrides: &[Override],
+) -> Result<CookConfig> {
+    let unit_config = interpolated_config(c, package, triple)?;
+    validate::report(
+        validate::overridden(validate::recipe(&unit_config.cook), overrides),
+        recipe,
+    )?;
+    Ok(unit_config)
+}
+
+fn interpolated_config(c: &CookConfig, package: &Package, triple: &str) -> Result<CookConfig> {
+    let root = Path::new(&package.manifest_path).parent().unwrap();
+    let variables = interpolate::Variables::new(
+        &package.name,
+        &package.version,
+        triple,
+        build::recipe_profile(&c.cook),
+        root.to_path_buf(),
     );
+    Ok(CookConfig {
+        cook: interpolate::interpolate(&c.cook, &variables)?,
+    })
+}
 
-    #[cfg(feature = "deploy")]
-    deploy(&cook_config);
+/// Runs the pre-cook hook, builds the package for all the target triples and
+/// verifies that its containers are reproducible if it is asked to.
+fn prepare_package(
+    recipe: &CookConfig,
+    m: &Metadata,
+    package: &Package,
+    o: &CookOptions,
+    names: &mut HashSet<String>,
+) -> Result<Prepared> {
+    let host = build::host_triple()?;
+    // The triples, the hooks and the vendored sources don't depend on the
+    // target, so the recipe for the host is used for them.
+    let c = &unit_config(recipe, package, &host, o.recipe, &o.overrides)?;
+    if o.verify_reproducible && c.cook.reproducible != Some(true) {
+        return Err(CookError::Config(
+            "The --verify-reproducible option needs reproducible = true in the recipe, \
+             the containers can't be reproducible without it."
+                .to_owned(),
+        ));
+    }
+    let triples = target_triples(c, o)?;
+    let pkg_name = &format!("{} v{}", package.name, package.version);
+    term_println(term::color::BRIGHT_GREEN, "Cooking", pkg_name);
+    message::emit(
+        "cook-started",
+        json!({
+            "package": package.name,
+            "version": package.version,
+            "targets": triples
+                .iter()
+                .map(|t| t.as_deref().unwrap_or(&host))
+                .collect::<Vec<_>>(),
+            "dry_run": o.dry_run,
+        }),
+    );
+    // A hook which can't be executed is better found before the build.
+    validate::report(
+        validate::overridden(validate::hooks(&c.cook), &o.overrides),
+        o.recipe,
+    )?;
+    cook_hook(&c.cook, true, o.dry_run)?;
 
-    cook_hook(&cook_config.cook, false);
-    term_println(term::color::BRIGHT_GREEN, "Finished", "cooking");
+    let mut units = Vec::new();
+    if o.vendor {
+        claim_archive_names(c, package, None, true, names)?;
+        if o.verify_reproducible && !o.dry_run {
+            let staging = staging_directory().join(&package.name);
+            verify_reproducible(c, package, None, true, || {
+                vendor::collect(m, package, &c.cook.cook_directory, &staging, false)
+            })?;
+        }
+        units.push((None, c.clone()));
+    } else {
+        for triple in &triples {
+            let triple = triple.as_deref();
+            let c = &unit_config(
+                recipe,
+                package,
+                triple.unwrap_or(&host),
+                o.recipe,
+                &o.overrides,
+            )?;
+            claim_archive_names(c, package, triple, false, names)?;
+            // The pre-cook hook may have made the ingredients, which is why
+            // they are only checked after it.
+            if !o.dry_run {
+                validate::report(
+                    validate::overridden(validate::ingredients(&c.cook), &o.overrides),
+                    o.recipe,
+                )?;
+            }
+            if let Some(ref b) = c.cook.build {
+                let artifacts = c.cook.artifact.as_deref().unwrap_or(&[]);
+                build::build(b, &package.name, triple, artifacts, o.dry_run)?;
+            }
+
+            if o.verify_reproducible && !o.dry_run {
+                verify_reproducible(c, package, triple, false, || {
+                    collect(c, m, package, triple, &host, false)
+                })?;
+            }
+            units.push((triple.map(str::to_owned), c.clone()));
+        }
+    }
+
+    Ok(Prepared {
+        config: c.clone(),
+        units,
+    })
 }
 
-fn collect_recursively(source: &str, destination: &str, files: &mut container::Files) {
+/// Adds the archives of the package to the names, fails if one of them is
+/// already there: it would be overwritten.
+fn claim_archive_names(
+    c: &CookConfig,
+    package: &Package,
+    triple: Option<&str>,
+    vendor: bool,
+    names: &mut HashSet<String>,
+) -> Result<()> {
+    for cont in &c.cook.containers {
+        let f = archive_file_name(&c.cook, package, cont, triple, vendor);
+        if !names.insert(f.clone()) {
+            return Err(CookError::Config(format!(
+                "{} is cooked more than once, use {{name}} and {{target}} in the archive_name.",
+                f
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Collects the files of the built package and packs them for all the
+/// target triples.
+fn cook_package(p: &Prepared, m: &Metadata, package: &Package, o: &CookOptions) -> Result<Cooked> {
+    let host = build::host_triple()?;
+    // The cooked files with the recipe they have been cooked by.
+    let mut cooked = Vec::new();
+    for (triple, c) in &p.units {
+        let triple = triple.as_deref();
+        let files = if o.vendor {
+            let staging = staging_directory().join(&package.name);
+            vendor::collect(m, package, &c.cook.cook_directory, &staging, o.dry_run)?
+        } else {
+            collect(c, m, package, triple, &host, o.dry_run)?
+        };
+        let files = archive(c, package, triple, o.vendor, files, o.dry_run)?;
+        cooked.push((c.clone(), files));
+    }
+
+    Ok(Cooked {
+        config: p.config.clone(),
+        files: cooked,
+    })
+}
+
+/// Returns the entries of the ingredient directory as `(name, path)`.
+fn read_ingredient_directory(path: &Path) -> Result<Vec<(String, String)>> {
+    let read_error = |e: std::io::Error| {
+        CookError::Ingredient(format!("Unable to read {}: {}", path.display(), e))
+    };
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(path).map_err(read_error)? {
+        let e = entry.map_err(read_error)?;
+        entries.push((
+            e.file_name().to_string_lossy().into_owned(),
+            path_str(&e.path())?.to_owned(),
+        ));
+    }
+    Ok(entries)
+}
+
+fn collect_recursively(
+    source: &str,
+    destination: &str,
+    files: &mut container::Files,
+) -> Result<()> {
     let path = Path::new(source);
     if !path.is_dir() {
-        panic!("{} is not a directory!", path.display());
-    }
-    for entry in fs::read_dir(path).unwrap() {
-        let e = entry.unwrap();
-        let name = e.file_name().into_string().unwrap();
-        files.push((
-            format!("{}/{}", destination.to_owned(), name),
-            e.path().to_str().unwrap().to_owned(),
-        ));
+        return Err(CookError::Ingredient(format!(
+            "{} is not a directory!",
+            path.display()
+        )));
+    }
+    for (name, entry_path) in read_ingredient_directory(path)? {
+        collect_entry(entry_path, format!("{}/{}", destination, name), files)?;
     }
+    Ok(())
 }
 
-fn collect(c: &CookConfig, cargo: &CargoConfig) -> container::Files {
+/// Collects the file or everything in the directory.
+fn collect_entry(path: String, destination: String, files: &mut container::Files) -> Result<()> {
+    if Path::new(&path).is_dir() {
+        collect_recursively(&path, &destination, files)
+    } else {
+        files.push((destination, path));
+        Ok(())
+    }
+}
+
+fn collect(
+    c: &CookConfig,
+    m: &Metadata,
+    package: &Package,
+    triple: Option<&str>,
+    host: &str,
+    dry_run: bool,
+) -> Result<container::Files> {
     let mut files = container::Files::new();
     if let Some(ref ingredients) = c.cook.ingredient {
         for i in ingredients {
             let path = Path::new(&i.source);
             if path.is_file() {
-                files.push((i.source.clone(), i.destination.clone()));
+                files.push((i.destination.clone(), i.source.clone()));
             } else if path.is_dir() {
                 if let Some(ref filter) = i.filter {
-                    let r = Regex::new(filter).unwrap();
-                    for entry in fs::read_dir(path).unwrap() {
-                        let e = entry.unwrap();
-                        let name = e.file_name().into_string().unwrap();
+                    let r = Regex::new(filter).map_err(|e| {
+                        CookError::Config(format!(
+                            "The filter of the {} ingredient is invalid: {}",
+                            i.source, e
+                        ))
+                    })?;
+                    for (name, entry_path) in read_ingredient_directory(path)? {
                         if r.is_match(&name) {
-                            files.push((
-                                format!("{}/{}", i.destination.clone(), name),
-                                e.path().to_str().unwrap().to_owned(),
-                            ));
+                            collect_entry(
+                                entry_path,
+                                format!("{}/{}", i.destination, name),
+                                &mut files,
+                            )?;
                         }
                     }
                 } else {
-                    collect_recursively(&i.source, &i.destination, &mut files);
+                    collect_recursively(&i.source, &i.destination, &mut files)?;
                 }
+            } else if dry_run && !path.exists() {
+                // The ingredients are only checked after the pre-cook hook,
+                // which isn't run in a dry run.
+                term_println(
+                    term::color::YELLOW,
+                    "Would pack",
+                    &format!(
+                        "{} as {} (made by the pre-cook hook?)",
+                        i.source, i.destination
+                    ),
+                );
             } else {
-                panic!(
+                return Err(CookError::Ingredient(format!(
                     "Specified ingredient ({}) is neither a file nor a directory.",
                     i.source
-                );
+                )));
             }
         }
     }
 
-    let target_file_name = format!("{}/{}", c.cook.target_directory, cargo.package.name);
-    let renamed_target_file_name = if let Some(s) = c.cook.target_rename.clone() {
-        s
-    } else {
-        cargo.package.name.clone()
-    };
-    files.push((renamed_target_file_name, target_file_name));
-    files
+    let artifact_directory = build::artifact_directory(&c.cook, &m.target_directory, triple);
+    let mut artifacts = container::Files::new();
+    for a in artifact::artifacts(&c.cook, package) {
+        let file_name = artifact::file_name(&a, triple.unwrap_or(host))?;
+        let renamed_file_name = match a.rename {
+            Some(s) => s,
+            None => file_name.rsplit('/').next().unwrap().to_owned(),
+        };
+        artifacts.push((
+            renamed_file_name,
+            format!("{}/{}", artifact_directory, file_name),
+        ));
+    }
+
+    #[cfg(feature = "dependencies")]
+    {
+        if c.cook.include_dependencies.unwrap_or(false) {
+            collect_dependencies(
+                &c.cook,
+                &artifact_directory,
+                triple.unwrap_or(host),
+                &mut artifacts,
+                dry_run,
+            )?;
+        }
+    }
+
+    files.extend(artifacts);
+    Ok(files)
 }
 
-fn archive(c: &CookConfig, cargo: &CargoConfig, cf: container::Files) {
-    std::fs::create_dir_all(&c.cook.cook_directory).unwrap();
+/// Returns the directory for the files which are generated while cooking.
+fn staging_directory() -> PathBuf {
+    std::env::temp_dir().join(format!("cargo-cook-{}", std::process::id()))
+}
 
-    for cont in &c.cook.containers {
-        let file_name = &format!(
-            "{}/{}-{}",
-            c.cook.cook_directory, cargo.package.name, cargo.package.version
-        );
-        let archive_file_name = &format!("{}.{}", file_name, cont);
-        // Archive
-        container::compress(&cf, archive_file_name, cont);
+/// Adds the shared libraries the artifacts depend on to the `lib/` directory
+/// and sets the RPATH of the artifacts if the recipe asks to.
+///
+/// In a dry run only the already built artifacts are inspected.
+#[cfg(feature = "dependencies")]
+fn collect_dependencies(
+    c: &Cook,
+    artifact_directory: &str,
+    triple: &str,
+    artifacts: &mut container::Files,
+    dry_run: bool,
+) -> Result<()> {
+    let search_paths = dependencies::search_paths(
+        artifact_directory,
+        c.library_paths.as_deref().unwrap_or(&[]),
+    );
+    let sources: Vec<String> = artifacts
+        .iter()
+        .map(|a| a.1.clone())
+        .filter(|a| !dry_run || Path::new(a).is_file())
+        .collect();
+    let libraries = dependencies::collect(&sources, &search_paths)?;
 
-        // Hash
-        if let Some(ref hashes) = c.cook.hashes {
-            for hash_type in hashes {
-                let hash_file_name = &format!("{}.{}", archive_file_name, hash_type);
-                hash::write_file_hash(archive_file_name, hash_file_name, hash_type);
+    if let Some(ref rpath) = c.rpath {
+        let staging = staging_directory().join(triple);
+        for a in artifacts.iter_mut() {
+            if dry_run {
+                term_println(
+                    term::color::YELLOW,
+                    "Would set",
+                    &format!("the RPATH of {} to {}", a.1, rpath),
+                );
+                continue;
+            }
+            if let Some(copy) = dependencies::rewrite_rpath(&a.1, rpath, &staging)? {
+                a.1 = copy;
             }
         }
+    }
 
-        let archive_file_path = Path::new(archive_file_name).canonicalize().unwrap();
-        term_println(
-            term::color::BRIGHT_GREEN,
-            "Cooked",
-            &format!("{}", archive_file_path.display()),
-        );
+    for l in libraries {
+        let name = path_str(Path::new(l.file_name().unwrap()))?;
+        artifacts.push((format!("lib/{}", name), path_str(&l)?.to_owned()));
     }
+    Ok(())
 }
 
-// TODO implement uploading the cooked archives: filesystem, ssh, git, ftp, http, etc
-#[cfg(feature = "deploy")]
-fn deploy(c: &CookConfig) {
-    if let Some(ref deploy) = c.cook.deploy {
-        if let Some(ref targets) = deploy.targets {
-            term_println(term::color::BRIGHT_GREEN, "Deploying", "the crate.");
-
-            for t in targets {
-                let target_str = format!("[{}]", t);
-                if let Err(e) = deploy::deploy(t, &c.cook.cook_directory, deploy) {
-                    term_println(term::color::BRIGHT_RED, &target_str, &e);
-                } else {
-                    term_println(term::color::BRIGHT_GREEN, &target_str, "OK");
-                }
-            }
-        }
+/// Returns the path of the archive in the container: the `archive_name` of the
+/// container or of the recipe, `<name>-<version>[-<target>]` by default, in
+/// the cook directory.
+fn archive_file_name(
+    c: &Cook,
+    package: &Package,
+    container: &str,
+    triple: Option<&str>,
+    vendor: bool,
+) -> String {
+    let template = c
+        .container
+        .as_ref()
+        .and_then(|m| m.get(container))
+        .and_then(|cont| cont.archive_name.as_ref())
+        .or(c.archive_name.as_ref());
+    let mut name = match (template, triple) {
+        (Some(t), _) => t.clone(),
+        (None, Some(t)) => format!("{}-{}-{}", package.name, package.version, t),
+        (None, None) => format!("{}-{}", package.name, package.version),
+    };
+    if vendor {
+        name = format!("{}-{}", name, VENDOR_SUFFIX);
     }
+    format!("{}/{}.{}", c.cook_directory, name, container)
 }
 
-fn load_config<T: std::fmt::Debug + serde::de::DeserializeOwned>(file_name: &str) -> T {
-    let config_toml = match fs::read_to_string(file_name) {
-        Ok(s) => s,
-        Err(e) => panic!("Unable to read {}: {}", file_name, e),
-    };
-    toml::de::from_str::<T>(&config_toml)
-        .unwrap_or_else(|e| panic!("Unable to parse {}: {}", file_name, e))
+fn container_options(c: &Cook, container: &str) -> container::Options {
+    container::Options {
+        reproducible: c.reproducible.unwrap_or(false),
+        level: c
+            .container
+            .as_ref()
+            .and_then(|m| m.get(container))
+            .and_then(|c| c.level),
+    }
 }
 
-fn cook_hook(c: &Cook, pre: bool) -> bool {
-    let hook = if pre { &c.pre_cook } else { &c.post_cook };
-    let hook_name = if pre { "Pre-cook" } else { "Post-cook" };
+/// Collects and packs the files twice into separate directories and fails if
+/// the containers are not byte-identical, telling which entries differ.
+fn verify_reproducible<F>(
+    c: &CookConfig,
+    package: &Package,
+    triple: Option<&str>,
+    vendor: bool,
+    collect: F,
+) -> Result<()>
+where
+    F: Fn() -> Result<container::Files>,
+{
+    let root = staging_directory().join("reproducible");
+    let mut runs = Vec::new();
+    for run in &["first", "second"] {
+        let directory = root.join(run);
+        fs::create_dir_all(&directory).map_err(|e| {
+            CookError::Container(format!("Unable to create {}: {}", directory.display(), e))
+        })?;
+        let files = collect()?;
+        let mut containers = Vec::new();
+        for cont in &c.cook.containers {
+            let name = archive_file_name(&c.cook, package, cont, triple, vendor);
+            let path = directory.join(Path::new(&name).file_name().unwrap());
+            let path = path_str(&path)?.to_owned();
+            container::compress(&files, &path, cont, &container_options(&c.cook, cont), &[])?;
+            containers.push(path);
+        }
+        runs.push(containers);
+    }
 
-    if let Some(ref pre) = *hook {
-        term_println(term::color::YELLOW, "Executing", hook_name);
-        let res = Command::new(Path::new(pre).canonicalize().unwrap()).status();
-        if let Ok(s) = res {
-            if s.success() {
-                term_println(
-                    term::color::BRIGHT_GREEN,
-                    hook_name,
-                    &format!("returned {}", s.code().unwrap_or(0i32)),
-                );
-            } else {
-                term_println(
-                    term::color::BRIGHT_RED,
-                    hook_name,
-                    &format!("returned {}", s.code().unwrap_or(0i32)),
-                );
-            }
-            return s.success();
+    let mut problems = 0usize;
+    for (i, cont) in c.cook.containers.iter().enumerate() {
+        let differences = verify::compare(&runs[0][i], &runs[1][i], cont)?;
+        let name = archive_file_name(&c.cook, package, cont, triple, vendor);
+        message::emit(
+            "reproducibility-verified",
+            json!({
+                "package": package.name,
+                "container": cont,
+                "path": name,
+                "reproducible": differences.is_empty(),
+                "differences": differences,
+            }),
+        );
+        if differences.is_empty() {
+            term_println(term::color::BRIGHT_GREEN, "Reproducible", &name);
         } else {
-            panic!("{} failed: {}", hook_name, res.unwrap_err());
+            term_println(term::color::BRIGHT_RED, "Not reproducible", &name);
+            for d in &differences {
+                term_println(term::color::BRIGHT_RED, "Differs", d);
+            }
+            problems += 1;
         }
     }
+    let _ = fs::remove_dir_all(&root);
 
-    true
+    if problems > 0 {
+        return Err(CookError::Verification(format!(
+            "{} container(s) are not reproducible",
+            problems
+        )));
+    }
+    Ok(())
 }
 
-fn parse_config(c: &CookConfig) {
+/// Packs the files into all the containers and hashes them.
+///
+/// Returns the paths of the cooked files: the containers and the hash files.
+fn archive(
+    c: &CookConfig,
+    package: &Package,
+    triple: Option<&str>,
+    vendor: bool,
+    cf: container::Files,
+    dry_run: bool,
+) -> Result<Vec<String>> {
+    let mut cooked = Vec::new();
+    for f in &cf {
+        message::emit(
+            "file-collected",
+            json!({ "package": package.name, "name": f.0, "source": f.1 }),
+        );
+    }
+    if dry_run {
+        for f in &cf {
+            term_println(
+                term::color::YELLOW,
+                "Would pack",
+                &format!("{} as {}", f.1, f.0),
+            );
+        }
+    }
+
+    let hash_types = c.cook.hashes.clone().unwrap_or_default();
     for cont in &c.cook.containers {
-        if !container::support_container(cont) {
-            panic!("The \"{}\" container type is unsupported.", cont);
+        let archive_file_name = &archive_file_name(&c.cook, package, cont, triple, vendor);
+        cooked.push(archive_file_name.clone());
+        let mut hashes = Vec::new();
+        if dry_run {
+            term_println(term::color::YELLOW, "Would cook", archive_file_name);
+        } else {
+            // Archive, the hashes are computed while it is being written.
+            let directory = Path::new(archive_file_name).parent().unwrap();
+            fs::create_dir_all(directory).map_err(|e| {
+                CookError::Container(format!("Unable to create {}: {}", directory.display(), e))
+            })?;
+            let options = container_options(&c.cook, cont);
+            hashes = container::compress(&cf, archive_file_name, cont, &options, &hash_types)?;
+        }
+
+        // Hash
+        let mut archive_hashes = serde_json::Map::new();
+        for hash_type in &hash_types {
+            let hash_file_name = &format!("{}.{}", archive_file_name, hash_type);
+            cooked.push(hash_file_name.clone());
+            if dry_run {
+                term_println(term::color::YELLOW, "Would hash", hash_file_name);
+            }
+        }
+        for (hash_type, hash) in hashes {
+            hash::write_hash_file(&format!("{}.{}", archive_file_name, hash_type), &hash)?;
+            archive_hashes.insert(hash_type, hash.into());
+        }
+
+        if !dry_run {
+            let archive_file_path = Path::new(archive_file_name).canonicalize().map_err(|e| {
+                CookError::Container(format!("Unable to find {}: {}", archive_file_name, e))
+            })?;
+            term_println(
+                term::color::BRIGHT_GREEN,
+                "Cooked",
+                &format!("{}", archive_file_path.display()),
+            );
+            let size = fs::metadata(&archive_file_path).map(|m| m.len()).ok();
+            message::emit(
+                "archive-written",
+                json!({
+                    "package": package.name,
+                    "container": cont,
+                    "path": archive_file_path,
+                    "size": size,
+                    "hashes": archive_hashes,
+                }),
+            );
         }
     }
+    Ok(cooked)
+}
 
-    if let Some(ref hashes) = c.cook.hashes {
-        for h in hashes {
-            if !hash::support_hash_type(h) {
-                panic!("The \"{}\" hash type is unsupported.", h);
+// TODO implement uploading the cooked archives: filesystem, ssh, git, ftp, http, etc
+/// Deploys the cooked files to every target, the first failure is returned.
+///
+/// The files going to the same target with the same settings are deployed
+/// together, whichever package and target triple they are cooked for.
+#[cfg(feature = "deploy")]
+fn deploy(cooked: &[Cooked], dry_run: bool) -> Result<()> {
+    let mut deploys: Vec<(&str, &Deploy, Vec<String>)> = Vec::new();
+    for (c, files) in cooked.iter().flat_map(|p| &p.files) {
+        let deploy = match c.cook.deploy {
+            Some(ref d) => d,
+            None => continue,
+        };
+        for t in deploy.targets.iter().flatten() {
+            match deploys
+                .iter_mut()
+                .find(|d| d.0.eq_ignore_ascii_case(t) && d.1 == deploy)
+            {
+                Some(d) => d.2.extend(files.iter().cloned()),
+                None => deploys.push((t, deploy, files.clone())),
             }
         }
     }
+    if deploys.is_empty() {
+        return Ok(());
+    }
 
-    #[cfg(feature = "deploy")]
-    let check_deploy = |c: &CookConfig| {
-        if let Some(ref deploy) = c.cook.deploy {
-            if let Some(ref targets) = deploy.targets {
-                for t in targets {
-                    if !deploy::support_deploy_target(t) {
-                        panic!("The \"{}\" deploy target is unsupported.", t);
-                    }
-                }
+    if dry_run {
+        for (t, deploy, files) in &deploys {
+            for step in deploy::plan(t, files, deploy) {
+                term_println(
+                    term::color::YELLOW,
+                    "Would deploy",
+                    &format!("[{}] {}", t, step),
+                );
             }
         }
-    };
+        return Ok(());
+    }
 
-    #[cfg(feature = "deploy")]
-    check_deploy(&c);
+    term_println(term::color::BRIGHT_GREEN, "Deploying", "the crate.");
+
+    let mut result = Ok(());
+    for (t, deploy, files) in &deploys {
+        let target_str = format!("[{}]", t);
+        let deployed = deploy::deploy(t, files, deploy);
+        message::emit(
+            "deploy-finished",
+            json!({
+                "target": t,
+                "success": deployed.is_ok(),
+                "message": deployed.as_ref().err(),
+            }),
+        );
+        if let Err(e) = deployed {
+            term_println(term::color::BRIGHT_RED, &target_str, &e);
+            if result.is_ok() {
+                result = Err(CookError::Deploy(format!(
+                    "Unable to deploy to {}: {}",
+                    t, e
+                )));
+            }
+        } else {
+            term_println(term::color::BRIGHT_GREEN, &target_str, "OK");
+        }
+    }
+    result
+}
+
+/// Executes the pre-cook or the post-cook hook, fails if it has failed.
+fn cook_hook(c: &Cook, pre: bool, dry_run: bool) -> Result<()> {
+    let hook = if pre { &c.pre_cook } else { &c.post_cook };
+    let hook_name = if pre { "Pre-cook" } else { "Post-cook" };
+    let hook_key = if pre { "pre_cook" } else { "post_cook" };
+
+    if let Some(ref pre) = *hook {
+        if dry_run {
+            term_println(
+                term::color::YELLOW,
+                "Would execute",
+                &format!("{} {}", hook_name, pre),
+            );
+            return Ok(());
+        }
+        term_println(term::color::YELLOW, "Executing", hook_name);
+        let path = Path::new(pre).canonicalize().map_err(|e| {
+            CookError::Hook(format!(
+                "Unable to find the {} hook {}: {}",
+                hook_name, pre, e
+            ))
+        })?;
+        let s = Command::new(path)
+            .stdout(message::child_stdout())
+            .status()
+            .map_err(|e| CookError::Hook(format!("{} failed: {}", hook_name, e)))?;
+        let code = s.code().unwrap_or(0i32);
+        message::emit(
+            "hook-finished",
+            json!({
+                "hook": hook_key,
+                "command": pre,
+                "success": s.success(),
+                "code": code,
+            }),
+        );
+        if !s.success() {
+            term_println(
+                term::color::BRIGHT_RED,
+                hook_name,
+                &format!("returned {}", code),
+            );
+            return Err(CookError::Hook(format!(
+                "{} hook {} returned {}",
+                hook_name, pre, code
+            )));
+        }
+        term_println(
+            term::color::BRIGHT_GREEN,
+            hook_name,
+            &format!("returned {}", code),
+        );
+    }
+
+    Ok(())
 }
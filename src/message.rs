@@ -0,0 +1,42 @@
+use serde_json::Value;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+pub const FORMATS: &[&str] = &["human", "json"];
+
+static JSON: AtomicBool = AtomicBool::new(false);
+
+pub fn set_format(format: &str) {
+    JSON.store(format == "json", Ordering::Relaxed);
+}
+
+/// Returns `true` if the stdout is taken by the JSON messages.
+pub fn is_json() -> bool {
+    JSON.load(Ordering::Relaxed)
+}
+
+/// Returns where the stdout of the child processes should go: the stderr
+/// when the JSON messages are printed, so that they don't get mixed.
+pub fn child_stdout() -> Stdio {
+    if is_json() {
+        Stdio::from(std::io::stderr())
+    } else {
+        Stdio::inherit()
+    }
+}
+
+/// Prints the message as a JSON line, if the JSON output is enabled.
+///
+/// The `reason` field tells what has happened, the rest are taken from the
+/// fields object.
+pub fn emit(reason: &str, fields: Value) {
+    if !is_json() {
+        return;
+    }
+    let mut message = match fields {
+        Value::Object(m) => m,
+        _ => serde_json::Map::new(),
+    };
+    message.insert("reason".to_owned(), Value::String(reason.to_owned()));
+    println!("{}", Value::Object(message));
+}
@@ -0,0 +1,167 @@
+use crate::config::{self, Cook};
+use crate::error::{CookError, Result};
+use crate::validate;
+use serde::Deserialize;
+use std::env;
+use std::path::Path;
+use std::process::Command;
+
+/// The key of the recipe in the `metadata` tables of `Cargo.toml`.
+const COOK_KEY: &str = "cook";
+/// The fields which are paths on this machine, relative to the `Cargo.toml`
+/// they are written in.
+const PATH_FIELDS: &[&str] = &[
+    "target_directory",
+    "pre_cook",
+    "post_cook",
+    "library_paths",
+    "cook_directory",
+    "deploy.fscopy.path",
+    "deploy.ssh.deploy_script",
+    "ingredient.source",
+];
+/// The tables of the recipes put on top of the `[cook]` one.
+const OVERRIDE_TABLES: &[&str] = &["profile", "member"];
+
+#[derive(Default, Debug, Clone, Eq, PartialEq, Deserialize)]
+pub struct Target {
+    pub name: String,
+    pub kind: Vec<String>,
+    pub crate_types: Vec<String>,
+}
+
+#[derive(Default, Debug, Clone, Eq, PartialEq, Deserialize)]
+pub struct Package {
+    pub id: String,
+    pub name: String,
+    pub version: String,
+    pub manifest_path: String,
+    pub targets: Vec<Target>,
+    /// The `[package.metadata]` table.
+    pub metadata: Option<serde_json::Value>,
+}
+
+/// The output of `cargo metadata`, only the fields we need.
+#[derive(Default, Debug, Clone, Eq, PartialEq, Deserialize)]
+pub struct Metadata {
+    pub packages: Vec<Package>,
+    pub workspace_members: Vec<String>,
+    pub workspace_root: String,
+    pub target_directory: String,
+    /// The `[workspace.metadata]` table.
+    #[serde(rename = "metadata")]
+    pub workspace_metadata: Option<serde_json::Value>,
+}
+
+impl Metadata {
+    /// Returns the workspace members in the order cargo lists them.
+    pub fn members(&self) -> Vec<&Package> {
+        self.workspace_members
+            .iter()
+            .filter_map(|id| self.packages.iter().find(|p| &p.id == id))
+            .collect()
+    }
+
+    /// Returns the recipe from `[workspace.metadata.cook]` with
+    /// `[package.metadata.cook]` of the package put on top of it, or `None`
+    /// if there is neither.
+    pub fn recipe(&self, package: Option<&Package>) -> Result<Option<Cook>> {
+        let parse_error = |e: &dyn std::fmt::Display| {
+            CookError::Config(format!("Unable to parse the cook metadata: {}", e))
+        };
+        let mut tables = Vec::new();
+        if let Some(t) = self
+            .workspace_metadata
+            .as_ref()
+            .and_then(|m| m.get(COOK_KEY))
+        {
+            tables.push((t, Path::new(&self.workspace_root), "workspace"));
+        }
+        if let Some(p) = package {
+            if let Some(t) = p.metadata.as_ref().and_then(|m| m.get(COOK_KEY)) {
+                tables.push((t, Path::new(&p.manifest_path).parent().unwrap(), "package"));
+            }
+        }
+
+        let mut recipe: Option<toml::Value> = None;
+        for (table, directory, section) in tables {
+            let mut table: toml::Value =
+                serde_json::from_value(table.clone()).map_err(|e| parse_error(&e))?;
+            validate::report(
+                validate::fields(&mut table, &format!("{}.metadata.{}", section, COOK_KEY)),
+                None,
+            )?;
+            let mut table = config::resolve(table, directory)?;
+            rebase_recipe(&mut table, directory);
+            match recipe {
+                Some(ref mut r) => config::merge(r, &table),
+                None => recipe = Some(table),
+            }
+        }
+        recipe
+            .map(|r| r.try_into().map_err(|e| parse_error(&e)))
+            .transpose()
+    }
+}
+
+/// Makes the relative paths of the recipe from `Cargo.toml` in the directory
+/// relative to the current one, where they are used from.
+fn rebase_recipe(recipe: &mut toml::Value, directory: &Path) {
+    let base = match env::current_dir() {
+        Ok(cwd) => directory.strip_prefix(&cwd).unwrap_or(directory).to_owned(),
+        Err(_) => directory.to_owned(),
+    };
+    if base.as_os_str().is_empty() {
+        return;
+    }
+    rebase_fields(recipe, &base);
+    for name in OVERRIDE_TABLES {
+        if let Some(toml::Value::Table(t)) = recipe.get_mut(name) {
+            for (_, overrides) in t.iter_mut() {
+                rebase_fields(overrides, &base);
+            }
+        }
+    }
+}
+
+fn rebase_fields(recipe: &mut toml::Value, base: &Path) {
+    for field in PATH_FIELDS {
+        let keys: Vec<&str> = field.split('.').collect();
+        rebase_value(recipe, &keys, base);
+    }
+}
+
+fn rebase_value(value: &mut toml::Value, keys: &[&str], base: &Path) {
+    match value {
+        toml::Value::Array(a) => a.iter_mut().for_each(|v| rebase_value(v, keys, base)),
+        toml::Value::Table(t) if !keys.is_empty() => {
+            if let Some(v) = t.get_mut(keys[0]) {
+                rebase_value(v, &keys[1..], base);
+            }
+        }
+        // The paths starting with a placeholder may be absolute ones.
+        toml::Value::String(s)
+            if keys.is_empty() && !s.starts_with('{') && Path::new(s.as_str()).is_relative() =>
+        {
+            *s = base.join(s.as_str()).to_string_lossy().into_owned();
+        }
+        _ => {}
+    }
+}
+
+/// Loads the metadata of the workspace the current directory belongs to.
+pub fn load() -> Result<Metadata> {
+    let cargo = crate::build::cargo();
+    let output = Command::new(&cargo)
+        .args(["metadata", "--format-version", "1", "--no-deps"])
+        .output()
+        .map_err(|e| CookError::Metadata(format!("Unable to run {}: {}", cargo, e)))?;
+    if !output.status.success() {
+        return Err(CookError::Metadata(format!(
+            "Unable to get the crate metadata: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    serde_json::from_slice(&output.stdout)
+        .map_err(|e| CookError::Metadata(format!("Unable to parse the crate metadata: {}", e)))
+}
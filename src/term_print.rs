@@ -1,5 +1,11 @@
+use crate::message;
+
 #[allow(dead_code)]
 pub fn term_rprint(color: term::color::Color, status_text: &str, text: &str) {
+    if message::is_json() {
+        eprint!("\r{} {}", status_text, text);
+        return;
+    }
     let mut t = term::stdout().unwrap();
 
     t.carriage_return().unwrap();
@@ -14,6 +20,10 @@ pub fn term_rprint(color: term::color::Color, status_text: &str, text: &str) {
 
 #[allow(dead_code)]
 pub fn term_rprint_finish() {
+    if message::is_json() {
+        eprintln!();
+        return;
+    }
     let mut t = term::stdout().unwrap();
 
     writeln!(t).unwrap();
@@ -29,7 +39,17 @@ pub fn term_println(color: term::color::Color, status_text: &str, text: &str) {
     term_print_(color, status_text, text, true);
 }
 
+/// Prints the text in colours, or as is to the stderr when the stdout is taken
+/// by the JSON messages.
 fn term_print_(color: term::color::Color, status_text: &str, text: &str, newline: bool) {
+    if message::is_json() {
+        if newline {
+            eprintln!("{} {}", status_text, text);
+        } else {
+            eprint!("{} {}", status_text, text);
+        }
+        return;
+    }
     let mut t = term::stdout().unwrap();
 
     t.attr(term::Attr::Bold).unwrap();
@@ -45,7 +65,6 @@ fn term_print_(color: term::color::Color, status_text: &str, text: &str, newline
     }
 }
 
-#[cfg(not(debug_assertions))]
-pub fn term_panic(text: &str) {
+pub fn term_error(text: &str) {
     term_println(term::color::BRIGHT_RED, "Failure:", text);
 }
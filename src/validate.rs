@@ -0,0 +1,763 @@
+use crate::config::{self, Cook, FieldType, Override};
+use crate::error::{CookError, Result};
+use crate::{artifact, container, hash, message};
+use regex::Regex;
+use serde_json::json;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// A problem of the recipe.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    /// The recipe field, `cook.deploy.ssh.remote_path`.
+    pub path: String,
+    pub message: String,
+    /// The error the problem stops cooking with.
+    pub error: fn(String) -> CookError,
+    /// Where the value comes from if not from the recipe file.
+    pub origin: Option<String>,
+}
+
+impl Diagnostic {
+    fn config(path: &str, message: String) -> Diagnostic {
+        Diagnostic {
+            path: path.to_owned(),
+            message,
+            error: CookError::Config,
+            origin: None,
+        }
+    }
+}
+
+/// The error of the whole recipe, an unknown placeholder for example.
+impl From<CookError> for Diagnostic {
+    fn from(e: CookError) -> Diagnostic {
+        Diagnostic::config("cook", e.to_string())
+    }
+}
+
+/// The position of a field in the recipe file: the line, the column and the
+/// length of its key, all of them start with 1.
+type Position = (usize, usize, usize);
+
+/// A recipe file which the diagnostics point into.
+struct Source {
+    name: String,
+    lines: Vec<String>,
+    /// The positions of the keys, the headers for the tables.
+    positions: HashMap<String, Position>,
+}
+
+impl Source {
+    fn read(name: &str) -> Option<Source> {
+        let text = fs::read_to_string(name).ok()?;
+        let lines: Vec<String> = text.lines().map(|l| l.to_owned()).collect();
+        let positions = locate(&lines);
+        Some(Source {
+            name: name.to_owned(),
+            lines,
+            positions,
+        })
+    }
+
+    /// Returns the position of the field or of the closest table it is in.
+    fn position(&self, path: &str) -> Option<Position> {
+        let mut path = path;
+        loop {
+            if let Some(p) = self.positions.get(path) {
+                return Some(*p);
+            }
+            path = &path[..path.rfind(['.', '['])?];
+        }
+    }
+}
+
+/// Returns the index of the character outside of the quoted strings.
+fn find_unquoted(s: &str, needle: char) -> Option<usize> {
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        match quote {
+            Some('"') if escaped => escaped = false,
+            Some('"') if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == needle => return Some(i),
+            None => {}
+        }
+    }
+    None
+}
+
+/// Splits the dotted key into its parts without the quotes.
+pub fn split_key(key: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut rest = key;
+    loop {
+        let end = find_unquoted(rest, '.').unwrap_or(rest.len());
+        let part = rest[..end].trim();
+        parts.push(part.trim_matches(|c| c == '"' || c == '\'').to_owned());
+        if end == rest.len() {
+            return parts;
+        }
+        rest = &rest[end + 1..];
+    }
+}
+
+/// Returns the key as it is written in the field paths: quoted if it is not
+/// a bare one.
+pub fn quote_key(key: &str) -> String {
+    let bare = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if bare {
+        key.to_owned()
+    } else {
+        format!("\"{}\"", key)
+    }
+}
+
+/// Returns the positions of all the keys and the table headers of the file.
+///
+/// It is not a TOML parser: the file is known to be valid, so it is enough to
+/// follow the headers, the `key = value` lines and the multi-line strings.
+fn locate(lines: &[String]) -> HashMap<String, Position> {
+    let mut positions = HashMap::new();
+    // The number of the tables of the arrays of tables seen so far.
+    let mut arrays: HashMap<String, usize> = HashMap::new();
+    let mut table = String::new();
+    let mut multiline = false;
+
+    for (n, line) in lines.iter().enumerate() {
+        let delimiters = line.matches("\"\"\"").count() + line.matches("'''").count();
+        if multiline {
+            multiline = delimiters % 2 == 0;
+            continue;
+        }
+        let trimmed = line.trim_start();
+        let column = line.len() - trimmed.len() + 1;
+
+        if trimmed.starts_with('[') {
+            let array = trimmed.starts_with("[[");
+            let inner = &trimmed[if array { 2 } else { 1 }..];
+            let end = match find_unquoted(inner, ']') {
+                Some(e) => e,
+                None => continue,
+            };
+            let keys = split_key(&inner[..end]);
+            let mut path = String::new();
+            for (i, k) in keys.iter().enumerate() {
+                if !path.is_empty() {
+                    path.push('.');
+                }
+                path.push_str(&quote_key(k));
+                if array && i + 1 == keys.len() {
+                    let count = arrays.entry(path.clone()).or_insert(0);
+                    *count += 1;
+                    path = format!("{}[{}]", path, *count - 1);
+                } else if let Some(count) = arrays.get(&path) {
+                    path = format!("{}[{}]", path, count - 1);
+                }
+            }
+            let closing = if array { "]]" } else { "]" };
+            let length = trimmed
+                .rfind(closing)
+                .map(|e| e + closing.len())
+                .unwrap_or_else(|| trimmed.len());
+            // The tables which are only implied by the header point to it too.
+            let mut parent = path.as_str();
+            while let Some(end) = parent.rfind(['.', '[']) {
+                parent = &parent[..end];
+                positions
+                    .entry(parent.to_owned())
+                    .or_insert((n + 1, column, length));
+            }
+            positions.insert(path.clone(), (n + 1, column, length));
+            table = path;
+            continue;
+        }
+        if trimmed.starts_with('#') {
+            continue;
+        }
+        if let Some(end) = find_unquoted(trimmed, '=') {
+            let key = trimmed[..end].trim_end();
+            let mut path = table.clone();
+            for k in split_key(key) {
+                if !path.is_empty() {
+                    path.push('.');
+                }
+                path.push_str(&quote_key(&k));
+            }
+            positions.insert(path, (n + 1, column, key.len()));
+            multiline = delimiters % 2 == 1;
+        }
+    }
+    positions
+}
+
+/// Returns the diagnostic as the compilers print them: with the file, the
+/// line and the snippet if the field is found in the file.
+fn render(d: &Diagnostic, source: Option<&Source>) -> String {
+    if let Some(ref origin) = d.origin {
+        return format!(
+            "error: {}\n  = note: the value is set by {}",
+            d.message, origin
+        );
+    }
+    let position = source.and_then(|s| s.position(&d.path).map(|p| (s, p)));
+    match position {
+        Some((s, (line, column, length))) => {
+            let number = line.to_string();
+            let margin = " ".repeat(number.len());
+            format!(
+                "error: {}\n{}--> {}:{}:{}\n{} |\n{} | {}\n{} | {}{}",
+                d.message,
+                margin,
+                s.name,
+                line,
+                column,
+                margin,
+                number,
+                s.lines[line - 1],
+                margin,
+                " ".repeat(column - 1),
+                "^".repeat(length.max(1))
+            )
+        }
+        None => format!("error: {}", d.message),
+    }
+}
+
+/// Fails with all the diagnostics if there are any, the file is the recipe
+/// the fields are taken from.
+pub fn report(mut diagnostics: Vec<Diagnostic>, file_name: Option<&str>) -> Result<()> {
+    if diagnostics.is_empty() {
+        return Ok(());
+    }
+    let source = file_name.and_then(Source::read);
+    let position = |d: &Diagnostic| match d.origin {
+        Some(_) => None,
+        None => source.as_ref().and_then(|s| s.position(&d.path)),
+    };
+    // As they are in the file, the ones which aren't found go last.
+    diagnostics.sort_by_key(|d| position(d).map_or(usize::MAX, |p| p.0));
+    let first = diagnostics[0].error;
+    let mut rendered = Vec::new();
+    for d in &diagnostics {
+        let position = position(d);
+        message::emit(
+            "recipe-diagnostic",
+            json!({
+                "path": d.path,
+                "message": d.message,
+                "file": position.and(file_name),
+                "line": position.map(|p| p.0),
+                "column": position.map(|p| p.1),
+                "origin": d.origin,
+            }),
+        );
+        rendered.push(render(d, source.as_ref()));
+    }
+    Err(first(format!(
+        "The recipe has {} problem(s):\n{}",
+        diagnostics.len(),
+        rendered.join("\n")
+    )))
+}
+
+/// Returns the diagnostics with the ones of the overridden fields pointing to
+/// the override which has set the field last.
+pub fn overridden(mut diagnostics: Vec<Diagnostic>, overrides: &[Override]) -> Vec<Diagnostic> {
+    for d in &mut diagnostics {
+        if let Some(o) = overrides.iter().rev().find(|o| o.sets(&d.path)) {
+            d.origin = Some(o.origin().to_owned());
+        }
+    }
+    diagnostics
+}
+
+/// Returns the number of the edits which turn one string into the other.
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let current = row[j + 1];
+            row[j + 1] = if ca == *cb {
+                previous
+            } else {
+                1 + previous.min(row[j]).min(row[j + 1])
+            };
+            previous = current;
+        }
+    }
+    row[b.len()]
+}
+
+/// Returns the known field of the table which the unknown key is a typo of.
+fn suggestion(table: &str, key: &str) -> Option<&'static str> {
+    config::FIELDS
+        .iter()
+        .filter_map(|(p, _)| {
+            let (parent, name) = match p.rfind('.') {
+                Some(i) => (&p[..i], &p[i + 1..]),
+                None => ("", *p),
+            };
+            if parent != table {
+                return None;
+            }
+            let d = distance(key, name);
+            let similar = d <= (name.len() / 3).max(1)
+                || (key.len() >= 3 && (name.starts_with(key) || key.starts_with(name)));
+            if similar {
+                Some((d, name))
+            } else {
+                None
+            }
+        })
+        .min()
+        .map(|(_, name)| name)
+}
+
+fn check_table(
+    table: &mut toml::value::Table,
+    schema: &str,
+    path: &str,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let mut wrong = Vec::new();
+    for (key, value) in table.iter_mut() {
+        let field = if schema.is_empty() {
+            key.clone()
+        } else {
+            format!("{}.{}", schema, key)
+        };
+        let field_path = format!("{}.{}", path, quote_key(key));
+        match config::field_type(&field) {
+            Some(t) => {
+                if !check_value(value, t, &field, &field_path, diagnostics) {
+                    wrong.push(key.clone());
+                }
+            }
+            None => {
+                let message = match suggestion(schema, key) {
+                    Some(s) => format!("Unknown field {}, did you mean {}?", field_path, s),
+                    None => format!("Unknown field {}.", field_path),
+                };
+                diagnostics.push(Diagnostic::config(&field_path, message));
+            }
+        }
+    }
+    for key in wrong {
+        table.remove(&key);
+    }
+}
+
+/// Returns whether the value is of the field type, the values of a wrong type
+/// inside it are taken out.
+fn check_value(
+    value: &mut toml::Value,
+    field_type: FieldType,
+    field: &str,
+    path: &str,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> bool {
+    use toml::Value;
+
+    if !field_type.matches(value) {
+        diagnostics.push(Diagnostic::config(
+            path,
+            format!("The {} must be {}.", path, field_type.describe()),
+        ));
+        return false;
+    }
+    match (field_type, value) {
+        (FieldType::Table, Value::Table(t)) => check_table(t, field, path, diagnostics),
+        (FieldType::TableList, Value::Array(a)) => {
+            for (i, item) in a.iter_mut().enumerate() {
+                if let Value::Table(t) = item {
+                    check_table(t, field, &format!("{}[{}]", path, i), diagnostics);
+                }
+            }
+        }
+        (FieldType::Map, Value::Table(t)) => {
+            let item_field = format!("{}.*", field);
+            let item_type = config::field_type(&item_field).expect("The map has no item type.");
+            let mut wrong = Vec::new();
+            for (name, item) in t.iter_mut() {
+                let item_path = format!("{}.{}", path, quote_key(name));
+                if !check_value(item, item_type, &item_field, &item_path, diagnostics) {
+                    wrong.push(name.clone());
+                }
+            }
+            for name in wrong {
+                t.remove(&name);
+            }
+        }
+        (FieldType::Recipe, Value::Table(t)) => check_table(t, "", path, diagnostics),
+        _ => {}
+    }
+    true
+}
+
+/// Returns the unknown fields and the fields of a wrong type of the
+/// `[cook]` table, the path is where the table is. The fields of a wrong
+/// type are taken out of the table, so the rest of it can be read.
+pub fn fields(cook: &mut toml::Value, path: &str) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    check_value(cook, FieldType::Recipe, "", path, &mut diagnostics);
+    diagnostics
+}
+
+/// Returns the problems of the recipe values, the recipe is the one of a
+/// package with the placeholders replaced.
+pub fn recipe(c: &Cook) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    for (i, cont) in c.containers.iter().enumerate() {
+        if !container::support_container(cont) {
+            diagnostics.push(Diagnostic::config(
+                &format!("cook.containers[{}]", i),
+                format!("The \"{}\" container type is unsupported.", cont),
+            ));
+        }
+    }
+    for (cont, settings) in c.container.iter().flatten() {
+        let path = format!("cook.container.{}", quote_key(cont));
+        if !container::support_container(cont) {
+            diagnostics.push(Diagnostic::config(
+                &path,
+                format!("The \"{}\" container type is unsupported.", cont),
+            ));
+            continue;
+        }
+        if let Some(level) = settings.level {
+            match container::levels(cont) {
+                Some((min, max)) if level < min || level > max => {
+                    diagnostics.push(Diagnostic::config(
+                        &format!("{}.level", path),
+                        format!(
+                            "The level of the \"{}\" container must be from {} to {}.",
+                            cont, min, max
+                        ),
+                    ))
+                }
+                Some(_) => {}
+                None => diagnostics.push(Diagnostic::config(
+                    &format!("{}.level", path),
+                    format!("The \"{}\" container doesn't compress.", cont),
+                )),
+            }
+        }
+    }
+
+    for (i, h) in c.hashes.iter().flatten().enumerate() {
+        if !hash::support_hash_type(h) {
+            diagnostics.push(Diagnostic::config(
+                &format!("cook.hashes[{}]", i),
+                format!("The \"{}\" hash type is unsupported.", h),
+            ));
+        }
+    }
+
+    #[cfg(not(feature = "dependencies"))]
+    {
+        if c.include_dependencies.unwrap_or(false) {
+            diagnostics.push(Diagnostic::config(
+                "cook.include_dependencies",
+                "The include_dependencies requires the \"dependencies\" feature.".to_owned(),
+            ));
+        }
+    }
+
+    for (i, ingredient) in c.ingredient.iter().flatten().enumerate() {
+        if let Some(ref filter) = ingredient.filter {
+            if let Err(e) = Regex::new(filter) {
+                diagnostics.push(Diagnostic::config(
+                    &format!("cook.ingredient[{}].filter", i),
+                    format!(
+                        "The filter of the {} ingredient is invalid: {}",
+                        ingredient.source, e
+                    ),
+                ));
+            }
+        }
+    }
+
+    for (i, a) in c.artifact.iter().flatten().enumerate() {
+        if !artifact::support_kind(&a.kind) {
+            diagnostics.push(Diagnostic::config(
+                &format!("cook.artifact[{}].kind", i),
+                format!("The \"{}\" artifact kind is unsupported.", a.kind),
+            ));
+        }
+    }
+
+    #[cfg(feature = "deploy")]
+    {
+        if let Some(ref deploy) = c.deploy {
+            deploy_problems(deploy, &mut diagnostics);
+        }
+    }
+
+    diagnostics
+}
+
+#[cfg(feature = "deploy")]
+fn deploy_problems(deploy: &config::Deploy, diagnostics: &mut Vec<Diagnostic>) {
+    for (i, t) in deploy.targets.iter().flatten().enumerate() {
+        let path = format!("cook.deploy.targets[{}]", i);
+        if !crate::deploy::support_deploy_target(t) {
+            diagnostics.push(Diagnostic::config(
+                &path,
+                format!("The \"{}\" deploy target is unsupported.", t),
+            ));
+            continue;
+        }
+        // The targets are case-insensitive, the sections are not.
+        let section = t.to_lowercase();
+        #[cfg(feature = "ssh")]
+        let configured = match section.as_str() {
+            "ssh" => deploy.ssh.is_some(),
+            _ => deploy.fscopy.is_some(),
+        };
+        #[cfg(not(feature = "ssh"))]
+        let configured = deploy.fscopy.is_some();
+        if !configured {
+            diagnostics.push(Diagnostic::config(
+                &path,
+                format!(
+                    "The \"{}\" deploy target has no [cook.deploy.{}] section.",
+                    t, section
+                ),
+            ));
+        }
+    }
+
+    #[cfg(feature = "ssh")]
+    {
+        if let Some(ref ssh) = deploy.ssh {
+            if !ssh.remote_path.starts_with('/') {
+                diagnostics.push(Diagnostic::config(
+                    "cook.deploy.ssh.remote_path",
+                    format!(
+                        "The remote_path must be an absolute path, not \"{}\".",
+                        ssh.remote_path
+                    ),
+                ));
+            }
+        }
+    }
+}
+
+/// Returns `true` if the file can be executed.
+fn is_executable(path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        path.metadata()
+            .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+            .unwrap_or(false)
+    }
+    #[cfg(not(unix))]
+    {
+        path.is_file()
+    }
+}
+
+/// Returns the hooks of the recipe which don't exist or can't be executed.
+pub fn hooks(c: &Cook) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let hooks = [("pre_cook", &c.pre_cook), ("post_cook", &c.post_cook)];
+    for (key, hook) in hooks.iter() {
+        if let Some(ref hook) = **hook {
+            let path = Path::new(hook);
+            let problem = if !path.exists() {
+                "does not exist"
+            } else if !is_executable(path) {
+                "is not executable"
+            } else {
+                continue;
+            };
+            diagnostics.push(Diagnostic {
+                path: format!("cook.{}", key),
+                message: format!("The {} hook {} {}.", key, hook, problem),
+                error: CookError::Hook,
+                origin: None,
+            });
+        }
+    }
+    diagnostics
+}
+
+/// Returns the ingredients of the recipe which don't exist.
+pub fn ingredients(c: &Cook) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    for (i, ingredient) in c.ingredient.iter().flatten().enumerate() {
+        if !Path::new(&ingredient.source).exists() {
+            diagnostics.push(Diagnostic {
+                path: format!("cook.ingredient[{}].source", i),
+                message: format!("The {} ingredient does not exist.", ingredient.source),
+                error: CookError::Ingredient,
+                origin: None,
+            });
+        }
+    }
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RECIPE: &str = r#"[cook]
+containers = ["tar"]
+"cook_directory" = "cooked"
+build.profile = "release"
+description = """
+hashes = ["md5"]
+"""
+[cook.container."tar.bzip2"]
+  level = 9
+[[cook.ingredient]]
+source = "src"
+[[cook.ingredient]]
+source = 'a=b'
+destination = "x""#;
+
+    fn source() -> Source {
+        let lines: Vec<String> = RECIPE.lines().map(|l| l.to_owned()).collect();
+        Source {
+            name: "Cook.toml".to_owned(),
+            positions: locate(&lines),
+            lines,
+        }
+    }
+
+    #[test]
+    fn keys() {
+        assert_eq!(split_key("cook"), ["cook"]);
+        assert_eq!(
+            split_key(r#"cook.container."tar.bzip2".level"#),
+            ["cook", "container", "tar.bzip2", "level"]
+        );
+        assert_eq!(split_key(" 'a.b' . c "), ["a.b", "c"]);
+        assert_eq!(find_unquoted(r#""a=b" = 1"#, '='), Some(6));
+        assert_eq!(find_unquoted(r#""a\"=" = 1"#, '='), Some(7));
+        assert_eq!(find_unquoted("'a=b'", '='), None);
+        assert_eq!(quote_key("tar"), "tar");
+        assert_eq!(quote_key("tar.bzip2"), "\"tar.bzip2\"");
+    }
+
+    #[test]
+    fn positions() {
+        let s = source();
+        let header = |h: &str| h.len();
+        assert_eq!(s.position("cook"), Some((1, 1, 6)));
+        assert_eq!(s.position("cook.containers"), Some((2, 1, 10)));
+        assert_eq!(s.position("cook.cook_directory"), Some((3, 1, 16)));
+        assert_eq!(s.position("cook.build.profile"), Some((4, 1, 13)));
+        // The keys in the multi-line strings are not keys.
+        assert_eq!(s.position("cook.hashes"), Some((1, 1, 6)));
+        let container = Some((8, 1, header(r#"[cook.container."tar.bzip2"]"#)));
+        assert_eq!(s.position(r#"cook.container."tar.bzip2""#), container);
+        assert_eq!(s.position("cook.container"), container);
+        assert_eq!(
+            s.position(r#"cook.container."tar.bzip2".level"#),
+            Some((9, 3, 5))
+        );
+        let ingredient = header("[[cook.ingredient]]");
+        assert_eq!(s.position("cook.ingredient[0]"), Some((10, 1, ingredient)));
+        assert_eq!(s.position("cook.ingredient[0].source"), Some((11, 1, 6)));
+        assert_eq!(s.position("cook.ingredient[1]"), Some((12, 1, ingredient)));
+        assert_eq!(s.position("cook.ingredient[1].source"), Some((13, 1, 6)));
+        // The last line has no line break after it.
+        assert_eq!(
+            s.position("cook.ingredient[1].destination"),
+            Some((14, 1, 11))
+        );
+        assert_eq!(
+            s.position("cook.ingredient[1].filter"),
+            Some((12, 1, ingredient))
+        );
+        assert_eq!(s.position("build"), None);
+    }
+
+    #[test]
+    fn snippet() {
+        let d = Diagnostic::config(
+            r#"cook.container."tar.bzip2".level"#,
+            "The level must be from 1 to 9.".to_owned(),
+        );
+        assert_eq!(
+            render(&d, Some(&source())),
+            "error: The level must be from 1 to 9.\n \
+             --> Cook.toml:9:3\n  |\n9 |   level = 9\n  |   ^^^^^"
+        );
+        assert_eq!(render(&d, None), "error: The level must be from 1 to 9.");
+    }
+
+    #[test]
+    fn suggestions() {
+        assert_eq!(distance("kitten", "sitting"), 3);
+        assert_eq!(distance("", "tar"), 3);
+        assert_eq!(distance("tar", "tar"), 0);
+
+        // Close enough: a third of the field name or a prefix.
+        assert_eq!(suggestion("", "hashs"), Some("hashes"));
+        assert_eq!(suggestion("", "targest"), Some("targets"));
+        assert_eq!(suggestion("", "cook_dir"), Some("cook_directory"));
+        assert_eq!(suggestion("deploy", "sshh"), Some("ssh"));
+        assert_eq!(suggestion("deploy.ssh", "hostnam"), Some("hostname"));
+        // Too far.
+        assert_eq!(suggestion("", "hsahse"), None);
+        assert_eq!(suggestion("deploy", "ftp"), None);
+        assert_eq!(suggestion("", "co"), None);
+        // Only the fields of the same table.
+        assert_eq!(suggestion("build", "hashs"), None);
+    }
+
+    #[test]
+    fn wrong_fields() {
+        let mut cook: toml::Value = toml::from_str(
+            r#"containers = ["tar"]
+cook_directory = 5
+unknwn = 1
+[container.tar]
+level = "9"
+[container.zip]
+level = 1
+"#,
+        )
+        .unwrap();
+        let paths: Vec<String> = fields(&mut cook, "cook")
+            .into_iter()
+            .map(|d| d.path)
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                "cook.container.tar.level",
+                "cook.cook_directory",
+                "cook.unknwn"
+            ]
+        );
+        // The values of a wrong type are taken out, the rest stays.
+        let expected: toml::Value = toml::from_str(
+            r#"containers = ["tar"]
+unknwn = 1
+[container.tar]
+[container.zip]
+level = 1
+"#,
+        )
+        .unwrap();
+        assert_eq!(cook, expected);
+    }
+}
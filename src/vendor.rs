@@ -0,0 +1,294 @@
+use crate::container::Files;
+use crate::error::{path_str, CookError, Result};
+use crate::metadata::{Metadata, Package};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const CARGO_LOCK: &str = "Cargo.lock";
+const CRATES_IO: &str = "registry+https://github.com/rust-lang/crates.io-index";
+const VENDOR_DIRECTORY: &str = "vendor";
+const VENDORED_SOURCES: &str = "vendored-sources";
+
+#[derive(Debug, Deserialize)]
+struct LockPackage {
+    name: String,
+    version: String,
+    source: Option<String>,
+    checksum: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Lock {
+    package: Option<Vec<LockPackage>>,
+}
+
+fn cargo_home() -> Result<PathBuf> {
+    match env::var_os("CARGO_HOME") {
+        Some(h) => Ok(PathBuf::from(h)),
+        None => env::var_os("HOME")
+            .map(|h| PathBuf::from(h).join(".cargo"))
+            .ok_or_else(|| CookError::Ingredient("The HOME is not set.".to_owned())),
+    }
+}
+
+/// Collects all the files under the directory, sorted, skipping VCS and cargo data.
+fn walk(dir: &Path, destination: &str, files: &mut Files) -> Result<()> {
+    let read_error = |e: std::io::Error| {
+        CookError::Ingredient(format!("Unable to read {}: {}", dir.display(), e))
+    };
+    let mut entries = fs::read_dir(dir)
+        .and_then(|d| d.collect::<std::io::Result<Vec<_>>>())
+        .map_err(read_error)?;
+    entries.sort_by_key(|e| e.file_name());
+    for e in entries {
+        let path = e.path();
+        let name = path_str(Path::new(&e.file_name()))?.to_owned();
+        if name == ".git" || name == ".cargo-ok" {
+            continue;
+        }
+        let dest = format!("{}/{}", destination, name);
+        if path.is_dir() {
+            walk(&path, &dest, files)?;
+        } else {
+            files.push((dest, path_str(&path)?.to_owned()));
+        }
+    }
+    Ok(())
+}
+
+/// Finds the unpacked sources of a registry package in the cargo cache.
+fn registry_source(p: &LockPackage) -> Result<PathBuf> {
+    let src = cargo_home()?.join("registry").join("src");
+    let dir_name = format!("{}-{}", p.name, p.version);
+    fs::read_dir(&src)
+        .into_iter()
+        .flatten()
+        .filter_map(|e| e.ok())
+        .map(|e| e.path().join(&dir_name))
+        .find(|p| p.join("Cargo.toml").is_file())
+        .ok_or_else(|| {
+            CookError::Ingredient(format!(
+                "{} is not in the cargo registry cache, run `cargo fetch` first.",
+                dir_name
+            ))
+        })
+}
+
+/// Finds the package with the name in a directory of a git checkout.
+fn find_package(dir: &Path, name: &str) -> Option<PathBuf> {
+    let manifest = dir.join("Cargo.toml");
+    if let Ok(s) = fs::read_to_string(&manifest) {
+        let is_package = toml::from_str::<toml::Value>(&s)
+            .ok()
+            .and_then(|v| v.get("package")?.get("name")?.as_str().map(|n| n == name))
+            .unwrap_or(false);
+        if is_package {
+            return Some(dir.to_path_buf());
+        }
+    }
+    let mut dirs: Vec<PathBuf> = fs::read_dir(dir)
+        .ok()?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_dir() && !p.ends_with(".git") && !p.ends_with("target"))
+        .collect();
+    dirs.sort();
+    dirs.iter().find_map(|d| find_package(d, name))
+}
+
+/// Finds the sources of a git package in the cargo checkouts.
+fn git_source(p: &LockPackage, source: &str) -> Result<PathBuf> {
+    let rev = source.rsplit('#').next().unwrap_or_default();
+    // Cargo names the checkouts by the short commit id.
+    let short_rev = &rev[..rev.len().min(7)];
+    let checkouts = cargo_home()?.join("git").join("checkouts");
+    fs::read_dir(&checkouts)
+        .into_iter()
+        .flatten()
+        .filter_map(|e| e.ok())
+        .map(|e| e.path().join(short_rev))
+        .filter(|d| d.is_dir())
+        .find_map(|d| find_package(&d, &p.name))
+        .ok_or_else(|| {
+            CookError::Ingredient(format!(
+                "{} {} is not in the cargo git checkouts, run `cargo fetch` first.",
+                p.name, source
+            ))
+        })
+}
+
+/// Returns the source replacement entry of `.cargo/config.toml` for a source.
+fn source_replacement(source: &str) -> Option<(String, String)> {
+    if source == CRATES_IO {
+        return None;
+    }
+    let source = source.split('#').next().unwrap();
+    let mut entry = String::new();
+    if let Some(git) = source.strip_prefix("git+") {
+        let (url, query) = match git.find('?') {
+            Some(i) => (&git[..i], Some(&git[i + 1..])),
+            None => (git, None),
+        };
+        entry.push_str(&format!("git = \"{}\"\n", url));
+        for (k, v) in query
+            .into_iter()
+            .flat_map(|q| q.split('&'))
+            .filter_map(|kv| {
+                let mut kv = kv.splitn(2, '=');
+                Some((kv.next()?, kv.next()?))
+            })
+        {
+            entry.push_str(&format!("{} = \"{}\"\n", k, v));
+        }
+    } else {
+        let registry = source.strip_prefix("registry+").unwrap_or(source);
+        entry.push_str(&format!("registry = \"{}\"\n", registry));
+    }
+    Some((source.to_owned(), entry))
+}
+
+fn cargo_config(sources: &BTreeMap<String, String>) -> String {
+    let mut s = format!(
+        "[source.crates-io]\nreplace-with = \"{}\"\n",
+        VENDORED_SOURCES
+    );
+    for (source, entry) in sources {
+        s.push_str(&format!(
+            "\n[source.\"{}\"]\n{}replace-with = \"{}\"\n",
+            source, entry, VENDORED_SOURCES
+        ));
+    }
+    s.push_str(&format!(
+        "\n[source.{}]\ndirectory = \"{}\"\n",
+        VENDORED_SOURCES, VENDOR_DIRECTORY
+    ));
+    s
+}
+
+/// Returns the files of the package as `cargo package` would take them,
+/// except for the ones in the cook directory.
+fn package_sources(package: &Package, cook_directory: &str, files: &mut Files) -> Result<()> {
+    let cargo = crate::build::cargo();
+    let output = Command::new(&cargo)
+        .args(["package", "--list", "--allow-dirty", "--package"])
+        .arg(&package.name)
+        .output()
+        .map_err(|e| CookError::Ingredient(format!("Unable to run {}: {}", cargo, e)))?;
+    if !output.status.success() {
+        return Err(CookError::Ingredient(format!(
+            "Unable to list the sources of {}: {}",
+            package.name,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    let root = Path::new(&package.manifest_path).parent().unwrap();
+    let cooked = Path::new(cook_directory).canonicalize().ok();
+    for file in String::from_utf8_lossy(&output.stdout).lines() {
+        let path = root.join(file);
+        if let Some(ref cooked) = cooked {
+            if path.starts_with(cooked) {
+                continue;
+            }
+        }
+        // Files generated by `cargo package` have nothing on the disk.
+        if path.is_file() {
+            files.push((file.to_owned(), path_str(&path)?.to_owned()));
+        }
+    }
+    Ok(())
+}
+
+/// Collects the sources of the package and of all its dependencies from
+/// `Cargo.lock` so that the crate can be built with `--offline`.
+///
+/// The generated files are written into the staging directory, unless it is
+/// a dry run.
+pub fn collect(
+    m: &Metadata,
+    package: &Package,
+    cook_directory: &str,
+    staging_directory: &Path,
+    dry_run: bool,
+) -> Result<Files> {
+    let write_error = |path: &Path, e: std::io::Error| {
+        CookError::Ingredient(format!("Unable to write {}: {}", path.display(), e))
+    };
+    let mut files = Files::new();
+    package_sources(package, cook_directory, &mut files)?;
+
+    let lock_path = Path::new(&m.workspace_root).join(CARGO_LOCK);
+    if !files.iter().any(|f| f.0 == CARGO_LOCK) {
+        files.push((CARGO_LOCK.to_owned(), path_str(&lock_path)?.to_owned()));
+    }
+    let lock = fs::read_to_string(&lock_path).map_err(|e| {
+        CookError::Ingredient(format!("Unable to read {}: {}", lock_path.display(), e))
+    })?;
+    let lock: Lock = toml::from_str(&lock).map_err(|e| {
+        CookError::Ingredient(format!("Unable to parse {}: {}", lock_path.display(), e))
+    })?;
+
+    let members = m.members();
+    let mut sources = BTreeMap::new();
+    for p in lock.package.unwrap_or_default() {
+        // Packages without a source are the workspace members and the path
+        // dependencies, the latter point outside of the cooked sources.
+        let source = match p.source {
+            Some(ref s) => s.clone(),
+            None if members
+                .iter()
+                .any(|w| w.name == p.name && w.version == p.version) =>
+            {
+                continue
+            }
+            None => {
+                return Err(CookError::Ingredient(format!(
+                    "Unable to vendor {} {}: path dependencies outside of the workspace \
+                     are not supported, make it a workspace member.",
+                    p.name, p.version
+                )))
+            }
+        };
+        let dir = if source.starts_with("git+") {
+            git_source(&p, &source)?
+        } else {
+            registry_source(&p)?
+        };
+        let vendored = format!("{}/{}-{}", VENDOR_DIRECTORY, p.name, p.version);
+        walk(&dir, &vendored, &mut files)?;
+
+        let checksum_path = staging_directory
+            .join(&vendored)
+            .join(".cargo-checksum.json");
+        if !dry_run {
+            let checksum = serde_json::json!({ "files": {}, "package": p.checksum });
+            fs::create_dir_all(checksum_path.parent().unwrap())
+                .and_then(|_| fs::write(&checksum_path, checksum.to_string()))
+                .map_err(|e| write_error(&checksum_path, e))?;
+        }
+        files.push((
+            format!("{}/.cargo-checksum.json", vendored),
+            path_str(&checksum_path)?.to_owned(),
+        ));
+
+        if let Some((source, entry)) = source_replacement(&source) {
+            sources.insert(source, entry);
+        }
+    }
+
+    let config_path = staging_directory.join(".cargo").join("config.toml");
+    if !dry_run {
+        fs::create_dir_all(config_path.parent().unwrap())
+            .and_then(|_| fs::write(&config_path, cargo_config(&sources)))
+            .map_err(|e| write_error(&config_path, e))?;
+    }
+    files.push((
+        ".cargo/config.toml".to_owned(),
+        path_str(&config_path)?.to_owned(),
+    ));
+    Ok(files)
+}
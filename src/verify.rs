@@ -0,0 +1,204 @@
+use crate::config::Cook;
+use crate::container;
+use crate::error::{CookError, Result};
+use crate::hash;
+use crate::term_print::*;
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+/// What is compared of the tar entries when the archives differ.
+#[derive(Debug)]
+struct Entry {
+    path: String,
+    mtime: u64,
+    mode: u32,
+    owner: (u64, u64),
+    size: u64,
+    /// The sha256 of the content.
+    content: String,
+}
+
+/// Returns the archives in the path: the path itself if it is a file or the
+/// files in the directory which have an extension of a configured container.
+fn archives(c: &Cook, path: &str) -> Result<Vec<String>> {
+    let p = Path::new(path);
+    if p.is_file() {
+        return Ok(vec![path.to_owned()]);
+    }
+    let mut archives: Vec<String> = fs::read_dir(p)
+        .map_err(|e| CookError::Verification(format!("Unable to read {}: {}", path, e)))?
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().is_file())
+        .filter_map(|e| e.path().to_str().map(|s| s.to_owned()))
+        .filter(|f| {
+            c.containers
+                .iter()
+                .any(|cont| f.ends_with(&format!(".{}", cont)))
+        })
+        .collect();
+    archives.sort();
+    Ok(archives)
+}
+
+/// Recomputes every configured hash of the archives in the path and compares
+/// them against the hash files next to the archives.
+///
+/// Fails if any hash file is missing or doesn't match.
+pub fn verify(c: &Cook, path: &str) -> Result<()> {
+    let hashes = match c.hashes {
+        Some(ref h) if !h.is_empty() => h,
+        _ => {
+            return Err(CookError::Config(
+                "The recipe has no hashes to verify.".to_owned(),
+            ))
+        }
+    };
+
+    let archives = archives(c, path)?;
+    if archives.is_empty() {
+        return Err(CookError::Verification(format!(
+            "There are no archives to verify in {}.",
+            path
+        )));
+    }
+
+    let mut problems = 0usize;
+    for archive in &archives {
+        // Every hash of the archive is computed in one pass.
+        let actual = hash::file_hashes(archive, hashes)?;
+        for (hash_type, hash) in &actual {
+            let hash_file_name = format!("{}.{}", archive, hash_type);
+            let label = format!("{} ({})", archive, hash_type);
+            let expected = match fs::read_to_string(&hash_file_name) {
+                Ok(s) => s
+                    .split_whitespace()
+                    .next()
+                    .unwrap_or_default()
+                    .to_lowercase(),
+                Err(_) => {
+                    term_println(term::color::BRIGHT_RED, "Missing", &hash_file_name);
+                    problems += 1;
+                    continue;
+                }
+            };
+            if *hash == expected {
+                term_println(term::color::BRIGHT_GREEN, "Verified", &label);
+            } else {
+                term_println(term::color::BRIGHT_RED, "Mismatch", &label);
+                problems += 1;
+            }
+        }
+    }
+
+    if problems > 0 {
+        return Err(CookError::Verification(format!(
+            "{} problem(s) in {} archive(s)",
+            problems,
+            archives.len()
+        )));
+    }
+    Ok(())
+}
+
+/// Returns `true` if the files have the same bytes.
+fn same_bytes(first: &str, second: &str) -> std::io::Result<bool> {
+    let mut first = BufReader::new(fs::File::open(first)?);
+    let mut second = BufReader::new(fs::File::open(second)?);
+    loop {
+        let (a, b) = (first.fill_buf()?, second.fill_buf()?);
+        if a.is_empty() || b.is_empty() {
+            return Ok(a.is_empty() && b.is_empty());
+        }
+        let n = a.len().min(b.len());
+        if a[..n] != b[..n] {
+            return Ok(false);
+        }
+        first.consume(n);
+        second.consume(n);
+    }
+}
+
+fn entries(path: &str, container: &str) -> Result<Vec<Entry>> {
+    let read_error =
+        |e: std::io::Error| CookError::Verification(format!("Unable to read {}: {}", path, e));
+    let mut archive = container::open(path, container)?;
+    let mut entries = Vec::new();
+    for entry in archive.entries().map_err(read_error)? {
+        let mut entry = entry.map_err(read_error)?;
+        let header = entry.header();
+        let mut e = Entry {
+            path: entry.path().map_err(read_error)?.display().to_string(),
+            mtime: header.mtime().map_err(read_error)?,
+            mode: header.mode().map_err(read_error)?,
+            owner: (
+                header.uid().map_err(read_error)?,
+                header.gid().map_err(read_error)?,
+            ),
+            size: header.size().map_err(read_error)?,
+            content: String::new(),
+        };
+        let mut content = hash::HashingWriter::new(std::io::sink(), &["sha256".to_owned()])?;
+        std::io::copy(&mut entry, &mut content).map_err(read_error)?;
+        e.content = content.finish().1.remove(0).1;
+        entries.push(e);
+    }
+    Ok(entries)
+}
+
+/// Returns how the entries of the two containers differ, nothing if the
+/// containers are byte-identical.
+pub fn compare(first: &str, second: &str, container: &str) -> Result<Vec<String>> {
+    let same = same_bytes(first, second).map_err(|e| {
+        CookError::Verification(format!("Unable to compare {} to {}: {}", first, second, e))
+    })?;
+    if same {
+        return Ok(Vec::new());
+    }
+
+    let (first, second) = (entries(first, container)?, entries(second, container)?);
+    let mut differences = Vec::new();
+    for a in &first {
+        let b = match second.iter().find(|b| b.path == a.path) {
+            Some(b) => b,
+            None => {
+                differences.push(format!("{}: only in the first archive", a.path));
+                continue;
+            }
+        };
+        let mut fields = Vec::new();
+        if a.mtime != b.mtime {
+            fields.push(format!("mtime {} != {}", a.mtime, b.mtime));
+        }
+        if a.mode != b.mode {
+            fields.push(format!("mode {:o} != {:o}", a.mode, b.mode));
+        }
+        if a.owner != b.owner {
+            fields.push(format!(
+                "owner {}:{} != {}:{}",
+                a.owner.0, a.owner.1, b.owner.0, b.owner.1
+            ));
+        }
+        if a.size != b.size || a.content != b.content {
+            fields.push("content".to_owned());
+        }
+        if !fields.is_empty() {
+            differences.push(format!("{}: {}", a.path, fields.join(", ")));
+        }
+    }
+    for b in second
+        .iter()
+        .filter(|b| first.iter().all(|a| a.path != b.path))
+    {
+        differences.push(format!("{}: only in the second archive", b.path));
+    }
+    if differences.is_empty() {
+        let order = |entries: &[Entry]| entries.iter().map(|e| e.path.clone()).collect::<Vec<_>>();
+        differences.push(if order(&first) != order(&second) {
+            "the entries are in a different order".to_owned()
+        } else {
+            "the entries are the same, the rest of the bytes differ".to_owned()
+        });
+    }
+    Ok(differences)
+}
@@ -0,0 +1,53 @@
+use crate::error::{CookError, Result};
+use crate::metadata::{Metadata, Package};
+use std::env;
+use std::path::Path;
+
+const CARGO_TOML: &str = "Cargo.toml";
+
+/// Returns the package whose manifest is in the current directory.
+fn current_package(m: &Metadata) -> Option<&Package> {
+    let manifest = env::current_dir()
+        .ok()?
+        .join(CARGO_TOML)
+        .canonicalize()
+        .ok()?;
+    m.members().into_iter().find(|p| {
+        Path::new(&p.manifest_path)
+            .canonicalize()
+            .map(|p| p == manifest)
+            .unwrap_or(false)
+    })
+}
+
+/// Returns the packages selected for cooking.
+///
+/// If neither the whole workspace nor any package has been requested
+/// explicitly, only the package in the current directory is cooked.
+pub fn packages(m: &Metadata, workspace: bool, specs: &[&str]) -> Result<Vec<Package>> {
+    let members = m.members();
+    if !specs.is_empty() {
+        return specs
+            .iter()
+            .map(|s| {
+                members
+                    .iter()
+                    .find(|p| p.name == *s)
+                    .map(|p| (*p).clone())
+                    .ok_or_else(|| {
+                        CookError::Metadata(format!("Package \"{}\" is not a workspace member.", s))
+                    })
+            })
+            .collect();
+    }
+    if workspace {
+        return Ok(members.into_iter().cloned().collect());
+    }
+    match current_package(m) {
+        Some(p) => Ok(vec![p.clone()]),
+        None => Err(CookError::Metadata(format!(
+            "{} is a virtual manifest, use --workspace or --package to choose what to cook.",
+            CARGO_TOML
+        ))),
+    }
+}